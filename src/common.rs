@@ -61,6 +61,38 @@ impl RelativePath {
         }
     }
 
+    /// Returns true if this path is equal to or below the given base path, comparing whole components
+    /// For example, "a/b/c" starts with "a/b" but not with "a/bc".  All paths start with the empty root path.
+    pub fn starts_with(&self, base: &RelativePath) -> bool {
+        let mut components = self.components();
+        base.components().all(|component| components.next() == Some(component))
+    }
+
+    /// Returns the remainder of this path below the given base path, or None if this path does not start with it
+    /// For example, "a/b/c" stripped of "a" is "b/c", and any path stripped of itself is the empty root path.
+    pub fn strip_prefix(&self, base: &RelativePath) -> Option<RelativePath> {
+        if !self.starts_with(base) {
+            None
+        } else if base.0.is_empty() {
+            Some(self.clone())
+        } else if self.0.len() == base.0.len() {
+            Some(RelativePath::default())
+        } else {
+            Some(RelativePath(self.0[base.0.len() + 1..].to_string()))
+        }
+    }
+
+    /// Returns the parent of this path, or None if this is the empty root path
+    /// The parent of a single component path is the empty root path
+    pub fn parent(&self) -> Option<RelativePath> {
+        if self.0.is_empty() {
+            None
+        } else {
+            let index = self.0.rfind('/').unwrap_or(0);
+            Some(RelativePath(self.0[..index].to_string()))
+        }
+    }
+
     /// Joins this relative path with another relative path, returning a new RelativePath
     /// Will return a RelativePathError if the other path is invalid
     pub fn try_join(&self, other: impl AsRef<str>) -> Result<RelativePath, RelativePathError> {
@@ -292,6 +324,52 @@ mod tests {
         assert_eq!(root_path.file_name(), None, "File name of empty path should be None");
     }
 
+    #[test]
+    fn test_relative_path_parent() {
+        let path = RelativePath::new("some/path/to/file.txt").unwrap();
+        let parent = path.parent().expect("Path should have a parent");
+        assert_eq!(parent.as_str(), "some/path/to", "Parent should be 'some/path/to'");
+
+        let path = RelativePath::new("file.txt").unwrap();
+        let parent = path.parent().expect("Single component path should have a parent");
+        assert!(
+            parent.is_empty(),
+            "Parent of a single component path should be the root path"
+        );
+
+        assert_eq!(parent.parent(), None, "Root path should have no parent");
+    }
+
+    #[test]
+    fn test_starts_with() {
+        let path = RelativePath::new("a/b/c").unwrap();
+        assert!(path.starts_with(&RelativePath::new("a/b").unwrap()));
+        assert!(path.starts_with(&RelativePath::new("a/b/c").unwrap()));
+        assert!(
+            path.starts_with(&RelativePath::default()),
+            "All paths should start with the root path"
+        );
+        assert!(
+            !path.starts_with(&RelativePath::new("a/bc").unwrap()),
+            "Partial components should not match"
+        );
+        assert!(!path.starts_with(&RelativePath::new("a/b/c/d").unwrap()));
+        assert!(!RelativePath::default().starts_with(&path));
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let path = RelativePath::new("a/b/c").unwrap();
+        assert_eq!(
+            path.strip_prefix(&RelativePath::new("a").unwrap()),
+            Some(RelativePath::new("b/c").unwrap())
+        );
+        assert_eq!(path.strip_prefix(&path), Some(RelativePath::default()));
+        assert_eq!(path.strip_prefix(&RelativePath::default()), Some(path.clone()));
+        assert_eq!(path.strip_prefix(&RelativePath::new("a/bc").unwrap()), None);
+        assert_eq!(path.strip_prefix(&RelativePath::new("b").unwrap()), None);
+    }
+
     #[test]
     fn test_relative_path_components() {
         let path = RelativePath::new("some/path/to/file.txt").unwrap();
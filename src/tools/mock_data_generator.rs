@@ -94,6 +94,7 @@ impl DirStack {
                 metadata,
                 change_state: Default::default(),
                 conflict_state: Default::default(),
+                lock: Default::default(),
             },
         ));
     }
@@ -1,24 +1,604 @@
 // == Std
-use std::error::Error;
+use std::{collections::BTreeMap, error::Error as StdError, num::NonZeroU32, ops::RangeBounds};
 
 // == Internal crates
-use super::model::Directory;
+use super::model::{
+    ChangeState, ChangeStateSet, Changelist, Changeset, ConflictInfo, ConflictStateSet, Directory, DirectoryEntry,
+    DirectoryPage, FileDiff, FileLock, FileRevision, IncomingChange, Label, PageCursor, PendingChange, RevertedFile,
+    Revision, Shelf, ShelfId, Stream, StreamType, SyncProgress, WatchEvent, WorkspaceSpec,
+};
 use crate::common::RelativePath;
 
+// == External crates
+use thiserror::Error;
+
+/// Errors that can be returned by a WorkspaceApi implementation
+#[derive(Debug, Error)]
+pub enum WorkspaceApiError {
+    #[error("The path '{0}' was not found")]
+    NotFound(RelativePath),
+    #[error("The path '{0}' is not a directory")]
+    NotADirectory(RelativePath),
+    #[error("The path '{0}' is a directory")]
+    IsADirectory(RelativePath),
+    #[error("The path '{0}' already exists")]
+    AlreadyExists(RelativePath),
+    #[error("The path '{0}' has no pending changes")]
+    NotOpened(RelativePath),
+    #[error("The path '{0}' has no unresolved conflicts")]
+    NotConflicted(RelativePath),
+    #[error("The path '{0}' is already opened as {1:?}")]
+    AlreadyOpened(RelativePath, ChangeState),
+    #[error("The path '{0}' is locked by {1}")]
+    LockedByOther(RelativePath, String),
+    #[error("The path '{0}' is not locked")]
+    NotLocked(RelativePath),
+    #[error("{} file(s) have pending changes which must be submitted or reverted first", .0.len())]
+    PendingChanges(Vec<RelativePath>),
+    #[error("The stream '{0}' was not found")]
+    StreamNotFound(String),
+    #[error("The stream '{0}' already exists")]
+    StreamAlreadyExists(String),
+    #[error("A {0:?} stream can't be created as a child stream")]
+    InvalidStreamType(StreamType),
+    #[error("The label '{0}' was not found")]
+    LabelNotFound(String),
+    #[error("The label '{0}' already exists")]
+    LabelAlreadyExists(String),
+    #[error("The shelf '{0}' was not found")]
+    ShelfNotFound(ShelfId),
+    #[error("There are no pending changes to shelve")]
+    NothingToShelve,
+    #[error("The workspace '{0}' was not found")]
+    WorkspaceNotFound(String),
+    #[error("The workspace '{0}' already exists")]
+    WorkspaceAlreadyExists(String),
+    #[error("The workspace '{0}' is in use")]
+    WorkspaceInUse(String),
+    #[error("There are no pending changes to submit")]
+    NothingToSubmit,
+    #[error("{} file(s) are out of date and must be synced first", .0.len())]
+    OutOfDate(Vec<RelativePath>),
+    #[error("{} file(s) have unresolved conflicts", .0.len())]
+    UnresolvedConflicts(Vec<RelativePath>),
+    #[error("The revision '{0}' was not found")]
+    RevisionNotFound(Revision),
+    #[error("Permission denied for path '{0}'")]
+    PermissionDenied(RelativePath),
+    #[error("Transport error: {0}")]
+    Transport(#[source] Box<dyn StdError + Send + Sync + 'static>),
+    #[error("The request timed out")]
+    Timeout,
+    #[error("The request was cancelled")]
+    Cancelled,
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+}
+
+impl WorkspaceApiError {
+    /// Returns true if the error is transient, and the same request may succeed if retried
+    pub fn is_retryable(&self) -> bool {
+        // Exhaustive, so every new variant has to be classified
+        match self {
+            WorkspaceApiError::Transport(_) | WorkspaceApiError::Timeout => true,
+            WorkspaceApiError::NotFound(_)
+            | WorkspaceApiError::NotADirectory(_)
+            | WorkspaceApiError::IsADirectory(_)
+            | WorkspaceApiError::AlreadyExists(_)
+            | WorkspaceApiError::NotOpened(_)
+            | WorkspaceApiError::NotConflicted(_)
+            | WorkspaceApiError::AlreadyOpened(_, _)
+            | WorkspaceApiError::LockedByOther(_, _)
+            | WorkspaceApiError::NotLocked(_)
+            | WorkspaceApiError::PendingChanges(_)
+            | WorkspaceApiError::StreamNotFound(_)
+            | WorkspaceApiError::StreamAlreadyExists(_)
+            | WorkspaceApiError::InvalidStreamType(_)
+            | WorkspaceApiError::LabelNotFound(_)
+            | WorkspaceApiError::LabelAlreadyExists(_)
+            | WorkspaceApiError::ShelfNotFound(_)
+            | WorkspaceApiError::NothingToShelve
+            | WorkspaceApiError::WorkspaceNotFound(_)
+            | WorkspaceApiError::WorkspaceAlreadyExists(_)
+            | WorkspaceApiError::WorkspaceInUse(_)
+            | WorkspaceApiError::NothingToSubmit
+            | WorkspaceApiError::OutOfDate(_)
+            | WorkspaceApiError::UnresolvedConflicts(_)
+            | WorkspaceApiError::RevisionNotFound(_)
+            | WorkspaceApiError::PermissionDenied(_)
+            | WorkspaceApiError::Cancelled
+            | WorkspaceApiError::Protocol(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct DirectoryFetchOptions {
     /// Specifies depth to fetch from the current directory, `None` means unlimited depth
     /// For example, a depth limit of 0 will only load the specified directory with no sub-directories
     pub depth_limit: Option<u32>,
     /// Optional filter string to filter directory entries by name (case-insensitive substring match)
-    /// NOTE: Currently not implemented in MockWorkspaceApi
+    /// Matching entries are kept along with every ancestor directory needed to reach them, and a matching directory
+    /// keeps all of its contents.  Non-matching entries are dropped, and aggregated states are recomputed over the
+    /// filtered tree.  The filter is applied before `depth_limit`, so a directory at the depth limit is kept (unloaded)
+    /// if it contains a match.
     pub filter_string: Option<String>,
+    /// Optional filter to only include files with one of the given change states
+    /// Directories are kept only when their aggregated change states intersect the filter, i.e. when they contain a
+    /// matching file.  Applied before `filter_string` and `depth_limit`.
+    pub change_state_filter: Option<ChangeStateSet>,
+    /// Optional filter to only include files with one of the given conflict states
+    /// Directories are kept only when their aggregated conflict states intersect the filter, i.e. when they contain a
+    /// matching file.  Applied before `filter_string` and `depth_limit`.
+    pub conflict_state_filter: Option<ConflictStateSet>,
+    /// Maximum number of entries to return in a page, `None` means no limit
+    /// NOTE: Only used by `fetch_directory_page`, `fetch_directory` always returns every entry
+    pub page_size: Option<NonZeroU32>,
+    /// Cursor from a previous `DirectoryPage` to continue the listing from, `None` starts from the first entry
+    /// NOTE: Only used by `fetch_directory_page`
+    pub cursor: Option<PageCursor>,
+    /// Selects a historical revision to fetch the directory as it was in the depot, `None` fetches the workspace
+    /// Historical directories have no pending changes, and return `WorkspaceApiError::RevisionNotFound` if the
+    /// revision has not been submitted yet.
+    pub revision: Option<RevisionSelector>,
+}
+
+/// A version of the workspace tree to compare, see `WorkspaceApi::diff_trees`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TreeVersion {
+    /// The workspace including its pending changes, without files opened for delete
+    Workspace,
+    /// The depot versions the workspace is based on, without any pending changes
+    Base,
+    /// A historical revision of the depot
+    Revision(RevisionSelector),
+}
+
+/// Selects a revision of the depot, see `DirectoryFetchOptions::revision`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RevisionSelector {
+    /// The given revision
+    Revision(Revision),
+    /// The newest revision submitted at or before the given time, in Unix milliseconds UTC
+    Timestamp(u64),
+    /// The file revisions tagged by the named label, paths outside the label's scope are not found
+    /// Returns `WorkspaceApiError::LabelNotFound` if the label does not exist.
+    Label(String),
+}
+
+/// The file revisions to tag with a new label, see `WorkspaceApi::create_label`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LabelSource {
+    /// The file revisions of a revision of the depot
+    Revision(RevisionSelector),
+    /// The depot revisions the workspace is based on, without any pending changes.  The label records the revision
+    /// the workspace was last synced to, which may be behind the head revision.
+    Workspace,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EntryFetchOptions {
+    /// Selects a historical revision to fetch the entry as it was in the depot, `None` fetches the workspace
+    /// Behaves as `DirectoryFetchOptions::revision`.
+    pub revision: Option<RevisionSelector>,
+    /// If set, a directory entry is returned loaded at the selected revision, as if fetched with `fetch_directory`
+    /// using these options.  Their own `revision` and paging options are ignored.
+    /// If `None`, directory entries are returned unloaded
+    pub directory_options: Option<DirectoryFetchOptions>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RevertOptions {
+    /// If set, local file content is left as it is, and only the pending changes are discarded
+    /// A file opened for add is still removed from the workspace, but remains on disk
+    pub keep_local_content: bool,
+    /// If set, only files opened for edit whose content is identical to the base version are reverted
+    pub unchanged_only: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryOptions {
+    /// Maximum number of revisions to return, `None` means no limit
+    pub limit: Option<NonZeroU32>,
+    /// If set, history continues past a move into the revisions of the file at its previous path
+    pub follow_renames: bool,
+}
+
+/// How to resolve a conflict, see `WorkspaceApi::resolve_conflict`
+#[derive(Debug, Clone)]
+pub enum Resolution {
+    /// Replace the workspace version of the file with their version, opening the file for delete if they deleted it
+    AcceptTheirs,
+    /// Keep the workspace version of the file, discarding their changes
+    AcceptYours,
+    /// Replace the workspace version of the file with the given merged content
+    Merged(Vec<u8>),
+}
+
+/// The pending changes to include in a submit
+#[derive(Debug, Clone)]
+pub enum SubmitTarget {
+    /// Every pending change at or below each of the given paths, regardless of changelist
+    Paths(Vec<RelativePath>),
+    /// Every pending change in the named changelist
+    Changelist(String),
 }
 
 pub trait WorkspaceApi {
+    /// Fetches the directory at the given path.
+    /// Paths are within the workspace, which only contains the depot files mapped by the view of its WorkspaceSpec,
+    /// and this holds for the paths taken and returned by every other method too.
+    /// Returns `WorkspaceApiError::NotFound` if the path does not exist, and `WorkspaceApiError::NotADirectory` if
+    /// the path names a file
     fn fetch_directory(
         &self,
         path: &RelativePath,
         options: DirectoryFetchOptions,
-    ) -> impl Future<Output = Result<Option<Directory>, Box<dyn Error>>>;
+    ) -> impl Future<Output = Result<Directory, WorkspaceApiError>>;
+
+    /// Fetches the directories at each of the given paths in a single request, using the same options for each.
+    /// Overlapping requests are merged: a path which is already loaded within the result for another requested path is
+    /// not returned separately, and can be found with `Directory::find_directory` on that result instead.  Each
+    /// returned path maps to its own result, with the same errors as `fetch_directory`, while the outer error is for
+    /// failures of the batch as a whole.
+    fn fetch_directories(
+        &self,
+        paths: &[RelativePath],
+        options: DirectoryFetchOptions,
+    ) -> impl Future<Output = Result<BTreeMap<RelativePath, Result<Directory, WorkspaceApiError>>, WorkspaceApiError>>;
+
+    /// Fetches the directory at the given path breadth-first, as a stream of directories ordered level by level.
+    /// The first item is the requested directory itself, and each item only contains its immediate entries, with all
+    /// sub-directories unloaded.  Every loaded sub-directory follows later in the stream, up to the `depth_limit`, and
+    /// can be attached to the tree with `Directory::insert_loaded_directory`.  Dropping the stream cancels the fetch.
+    /// Errors are as for `fetch_directory`, and end the stream.
+    fn fetch_directory_stream<'a>(
+        &'a self,
+        path: &RelativePath,
+        options: DirectoryFetchOptions,
+    ) -> impl futures::Stream<Item = Result<Directory, WorkspaceApiError>> + use<'a, Self>;
+
+    /// Fetches a page of the directory at the given path, using the `page_size` and `cursor` options.
+    /// Pagination applies to the immediate entries of the directory, which are ordered by their `RelativePath`, so a
+    /// listing continued from a cursor is stable even if entries are added or removed between pages.  The aggregated
+    /// states of the returned directory are not recomputed for the page, so they describe all of its entries.
+    /// Errors are as for `fetch_directory`.
+    fn fetch_directory_page(
+        &self,
+        path: &RelativePath,
+        options: DirectoryFetchOptions,
+    ) -> impl Future<Output = Result<DirectoryPage, WorkspaceApiError>>;
+
+    /// Fetches the single entry at the given path, which may be either a file or a directory.
+    /// Directories are returned unloaded unless `EntryFetchOptions::directory_options` is set.  The root path has no
+    /// name of its own, so is returned as a directory entry with an empty name.
+    /// Returns `WorkspaceApiError::NotFound` if the path does not exist, rather than `None`, so a missing path is
+    /// reported the same way as by every other method and can be told apart from a failed request by matching the
+    /// error alone.
+    fn fetch_entry(
+        &self,
+        path: &RelativePath,
+        options: EntryFetchOptions,
+    ) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Lists every file with pending changes at or below the given scope path, grouped by changelist.
+    /// The default changelist is always listed first, even if empty, followed by any other changelists containing
+    /// changes within the scope, ordered by name.
+    fn list_pending_changes(
+        &self,
+        scope: &RelativePath,
+    ) -> impl Future<Output = Result<Vec<Changelist>, WorkspaceApiError>>;
+
+    /// Moves the given files with pending changes into the named changelist, which is created if it does not exist.
+    /// Returns `WorkspaceApiError::NotOpened` if any of the files has no pending changes, in which case no files are
+    /// moved.
+    fn move_to_changelist(
+        &self,
+        paths: &[RelativePath],
+        changelist: &str,
+    ) -> impl Future<Output = Result<(), WorkspaceApiError>>;
+
+    /// Opens a new local file at the given path for add, returning the added entry.
+    /// Any missing parent directories are created.  Returns `WorkspaceApiError::AlreadyExists` if the path is already
+    /// in the workspace, and `WorkspaceApiError::NotFound` if there is no local file at the path.
+    fn mark_for_add(&self, path: &RelativePath) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Opens the file at the given path for edit, returning the updated entry with the current local metadata.
+    /// Files which are already opened for add or edit stay in that state.  Returns
+    /// `WorkspaceApiError::AlreadyOpened` if the file is opened for delete.
+    fn mark_for_edit(&self, path: &RelativePath) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Opens the file at the given path for delete, returning the updated entry.
+    /// Any pending edits to the file are discarded.  Returns `WorkspaceApiError::AlreadyOpened` if the file is opened
+    /// for add, which should be reverted instead.
+    fn mark_for_delete(&self, path: &RelativePath) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Moves the file at the `from` path to the `to` path, returning the entry at the new path.
+    /// The old path is opened for delete and the new path for add, both in the changelist of the original file, except
+    /// for a file which is already opened for add, which is simply renamed.  Any missing parent directories of the new
+    /// path are created.  Returns `WorkspaceApiError::AlreadyExists` if the new path is already in the workspace, and
+    /// `WorkspaceApiError::AlreadyOpened` if the file is opened for delete.
+    fn mark_for_move(
+        &self,
+        from: &RelativePath,
+        to: &RelativePath,
+    ) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Reverts every pending change at or below each of the given paths, returning the reverted files ordered by path.
+    /// Reverted files become unchanged, with the metadata of their base version unless `keep_local_content` is set, and
+    /// files opened for add are removed from the workspace.  Paths without pending changes are skipped.
+    fn revert(
+        &self,
+        paths: &[RelativePath],
+        options: RevertOptions,
+    ) -> impl Future<Output = Result<Vec<RevertedFile>, WorkspaceApiError>>;
+
+    /// Lists the unresolved and resolved conflicts for every file at or below the given scope path, ordered by path
+    fn list_conflicts(
+        &self,
+        scope: &RelativePath,
+    ) -> impl Future<Output = Result<Vec<ConflictInfo>, WorkspaceApiError>>;
+
+    /// Resolves the conflict for the file at the given path, returning the updated entry.
+    /// The file's conflict state becomes `ConflictState::Resolved`, so it can be submitted.  Returns
+    /// `WorkspaceApiError::NotConflicted` if the file has no unresolved conflict.
+    fn resolve_conflict(
+        &self,
+        path: &RelativePath,
+        resolution: Resolution,
+    ) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Locks the file at the given path for exclusive checkout by the current user, returning the updated entry.
+    /// While a file is locked, other users can't open it for edit, delete or move, or submit changes to it.  Locks are
+    /// released when changes to the file are submitted.  Locking a file already locked by the current user has no
+    /// effect.  Returns `WorkspaceApiError::LockedByOther` if another user holds the lock, and
+    /// `WorkspaceApiError::IsADirectory` if the path names a directory.
+    fn lock(&self, path: &RelativePath) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Unlocks the file at the given path, returning the updated entry.
+    /// Returns `WorkspaceApiError::NotLocked` if the file is not locked, and `WorkspaceApiError::LockedByOther` if
+    /// another user holds the lock.
+    fn unlock(&self, path: &RelativePath) -> impl Future<Output = Result<DirectoryEntry, WorkspaceApiError>>;
+
+    /// Lists the locks held by any user on files at or below the given scope path, ordered by path
+    fn list_locks(&self, scope: &RelativePath) -> impl Future<Output = Result<Vec<FileLock>, WorkspaceApiError>>;
+
+    /// Fetches the submitted revisions of the file at the given path, newest first.
+    /// History stops at the revision the file was moved to the path, unless `follow_renames` is set.  The path does
+    /// not need to exist in the workspace, so the history of a deleted file can be fetched.  Returns
+    /// `WorkspaceApiError::NotFound` if the path has no history and does not exist.
+    fn file_history(
+        &self,
+        path: &RelativePath,
+        options: HistoryOptions,
+    ) -> impl Future<Output = Result<Vec<FileRevision>, WorkspaceApiError>>;
+
+    /// Fetches the changeset submitted as the given revision.
+    /// Returns `WorkspaceApiError::RevisionNotFound` if no such revision has been submitted.
+    fn fetch_changeset(&self, id: Revision) -> impl Future<Output = Result<Changeset, WorkspaceApiError>>;
+
+    /// Lists the changesets with revisions in the given range, newest first.
+    /// If a path filter is given, only changesets affecting a file at or below the path are listed, but each listed
+    /// changeset still includes all of its changes.
+    fn list_changesets(
+        &self,
+        range: impl RangeBounds<Revision>,
+        path_filter: Option<&RelativePath>,
+    ) -> impl Future<Output = Result<Vec<Changeset>, WorkspaceApiError>>;
+
+    /// Compares the directory at the given scope path between two versions of the tree, returning a directory
+    /// containing only the files which differ, with each file's change state describing how it changed from `from` to
+    /// `to`.  See `model::diff_directories` for the details of the result.  The scope may be missing from one of the
+    /// versions, in which case all of its files are added or deleted, but returns `WorkspaceApiError::NotFound` if it
+    /// is missing from both.
+    fn diff_trees(
+        &self,
+        from: TreeVersion,
+        to: TreeVersion,
+        scope: &RelativePath,
+    ) -> impl Future<Output = Result<Directory, WorkspaceApiError>>;
+
+    /// Compares the content of the file at the given path between two versions of the tree, line by line.
+    /// The file may be missing from one of the versions, in which case it is diffed against empty content.  Returns
+    /// `WorkspaceApiError::NotFound` if it is missing from both, and `WorkspaceApiError::IsADirectory` if the path
+    /// names a directory.
+    fn diff_file(
+        &self,
+        path: &RelativePath,
+        from: TreeVersion,
+        to: TreeVersion,
+    ) -> impl Future<Output = Result<FileDiff, WorkspaceApiError>>;
+
+    /// Lists the changes syncing the files at or below the given scope path to the target revision would make, or to
+    /// the head revision if `None`, ordered by path.  Files with pending changes which also have incoming changes are
+    /// listed as `SyncAction::Conflict`.  Nothing in the workspace is changed.  Returns `WorkspaceApiError::NotFound`
+    /// if the scope is in neither the workspace nor the target revision.
+    fn preview_sync(
+        &self,
+        scope: &RelativePath,
+        target: Option<RevisionSelector>,
+    ) -> impl Future<Output = Result<Vec<IncomingChange>, WorkspaceApiError>>;
+
+    /// Syncs the files at or below the given scope path to the target revision, or to the head revision if `None`, as
+    /// a stream of progress yielded as each of the changes listed by `preview_sync` is made.  The stream is empty if
+    /// there is nothing to sync.  Synced files are no longer `ConflictState::Incoming` once they reach the head
+    /// revision.  Conflicting files keep their pending changes and become `ConflictState::Unresolved`, with the
+    /// details available from `list_conflicts`.  Dropping the stream cancels the sync, leaving the files synced so far
+    /// in place.  Errors are as for `preview_sync`, and end the stream.
+    fn sync<'a>(
+        &'a self,
+        scope: &RelativePath,
+        target: Option<RevisionSelector>,
+    ) -> impl futures::Stream<Item = Result<SyncProgress, WorkspaceApiError>> + use<'a, Self>;
+
+    /// Shelves every pending change in the named changelist with the given description, returning the new shelf.
+    /// The changes stay pending in the workspace.  Returns `WorkspaceApiError::NothingToShelve` if the changelist has
+    /// no pending changes.
+    fn shelve(&self, changelist: &str, description: &str) -> impl Future<Output = Result<Shelf, WorkspaceApiError>>;
+
+    /// Lists the shelves created by the given user, or by any user if `None`, ordered by id.
+    /// Only the root directory of each shelf's contents is loaded, use `fetch_shelf` for the shelved files.
+    fn list_shelves(&self, user_filter: Option<&str>) -> impl Future<Output = Result<Vec<Shelf>, WorkspaceApiError>>;
+
+    /// Fetches the shelf with the given id, with its contents fully loaded.
+    /// Returns `WorkspaceApiError::ShelfNotFound` if there is no such shelf.
+    fn fetch_shelf(&self, id: ShelfId) -> impl Future<Output = Result<Shelf, WorkspaceApiError>>;
+
+    /// Opens the shelved changes in the workspace in the named changelist, which is created if it does not exist,
+    /// returning the unshelved changes ordered by path.  The shelf is left as it is.  Returns
+    /// `WorkspaceApiError::AlreadyOpened` if any shelved file already has pending changes, and
+    /// `WorkspaceApiError::AlreadyExists` if a shelved add is already in the workspace, in which case nothing is
+    /// unshelved.
+    fn unshelve(
+        &self,
+        id: ShelfId,
+        changelist: &str,
+    ) -> impl Future<Output = Result<Vec<PendingChange>, WorkspaceApiError>>;
+
+    /// Creates a label tagging the file revisions at or below the given scope path, returning the new label.
+    /// Returns `WorkspaceApiError::LabelAlreadyExists` if the name is taken, and `WorkspaceApiError::NotFound` if the
+    /// scope does not exist in the source.
+    fn create_label(
+        &self,
+        name: &str,
+        source: LabelSource,
+        scope: &RelativePath,
+        description: &str,
+    ) -> impl Future<Output = Result<Label, WorkspaceApiError>>;
+
+    /// Lists every label in the current stream, ordered by name
+    fn list_labels(&self) -> impl Future<Output = Result<Vec<Label>, WorkspaceApiError>>;
+
+    /// Deletes the named label.  The labeled files are unaffected.  Returns `WorkspaceApiError::LabelNotFound` if the
+    /// label does not exist.
+    fn delete_label(&self, name: &str) -> impl Future<Output = Result<(), WorkspaceApiError>>;
+
+    /// Lists the definitions of every workspace, ordered by name
+    fn list_workspaces(&self) -> impl Future<Output = Result<Vec<WorkspaceSpec>, WorkspaceApiError>>;
+
+    /// Fetches the definition of the workspace this API operates on
+    fn current_workspace(&self) -> impl Future<Output = Result<WorkspaceSpec, WorkspaceApiError>>;
+
+    /// Creates a new workspace with the given definition, returning it.
+    /// Returns `WorkspaceApiError::WorkspaceAlreadyExists` if the name is taken.
+    fn create_workspace(&self, spec: WorkspaceSpec) -> impl Future<Output = Result<WorkspaceSpec, WorkspaceApiError>>;
+
+    /// Replaces the definition of the workspace with the same name, returning it.
+    /// Updating the view of the current workspace changes which files later calls can address.  Returns
+    /// `WorkspaceApiError::WorkspaceNotFound` if there is no workspace with the name.
+    fn update_workspace(&self, spec: WorkspaceSpec) -> impl Future<Output = Result<WorkspaceSpec, WorkspaceApiError>>;
+
+    /// Deletes the named workspace.  Returns `WorkspaceApiError::WorkspaceNotFound` if it does not exist, and
+    /// `WorkspaceApiError::WorkspaceInUse` if it is the current workspace.
+    fn delete_workspace(&self, name: &str) -> impl Future<Output = Result<(), WorkspaceApiError>>;
+
+    /// Lists every stream in the depot, ordered by name
+    fn list_streams(&self) -> impl Future<Output = Result<Vec<Stream>, WorkspaceApiError>>;
+
+    /// Fetches the stream the workspace is currently on
+    fn current_stream(&self) -> impl Future<Output = Result<Stream, WorkspaceApiError>>;
+
+    /// Creates a new stream branched from the head revision of the named parent stream, returning the new stream.
+    /// The new stream shares the parent's history up to that revision, and continues its revision numbering.  The
+    /// workspace stays on its current stream.  Returns `WorkspaceApiError::StreamNotFound` if the parent does not
+    /// exist, `WorkspaceApiError::StreamAlreadyExists` if the name is taken, and `WorkspaceApiError::InvalidStreamType`
+    /// for `StreamType::Mainline`, which can't have a parent.
+    fn create_stream(
+        &self,
+        name: &str,
+        parent: &str,
+        stream_type: StreamType,
+    ) -> impl Future<Output = Result<Stream, WorkspaceApiError>>;
+
+    /// Switches the workspace to the named stream, returning the stream.
+    /// Afterwards every fetch reflects the new stream, including fetches at historical revisions or labels, as do the
+    /// history and labels, which are kept per stream.  No watch events are emitted for the switch, so any cached state
+    /// should be re-fetched.  Returns `WorkspaceApiError::StreamNotFound` if the stream does not exist, and
+    /// `WorkspaceApiError::PendingChanges` if the workspace has pending changes, which would be lost.
+    fn switch_stream(&self, name: &str) -> impl Future<Output = Result<Stream, WorkspaceApiError>>;
+
+    /// Submits the targeted pending changes to the depot with the given description, returning the new revision.
+    /// Added and modified files become unchanged, and deleted files are removed from the workspace.  The submit is
+    /// rejected as a whole with `WorkspaceApiError::OutOfDate` if any included file has incoming changes, or
+    /// `WorkspaceApiError::UnresolvedConflicts` if any included file has unresolved conflicts.
+    fn submit(
+        &self,
+        target: SubmitTarget,
+        description: &str,
+    ) -> impl Future<Output = Result<Revision, WorkspaceApiError>>;
+
+    /// Watches the given path for changes, as a stream of events for the path itself and its immediate entries, or for
+    /// all entries below it if `recursive` is set.  The path does not need to exist yet, so the addition of an entry
+    /// can be watched for.  Events which occur after this call returns are delivered, and dropping the stream stops
+    /// watching.  An error is yielded if events were missed, for example if the watcher fell too far behind, after
+    /// which the client should re-fetch any cached state.
+    fn watch<'a>(
+        &'a self,
+        path: &RelativePath,
+        recursive: bool,
+    ) -> impl futures::Stream<Item = Result<WatchEvent, WorkspaceApiError>> + use<'a, Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<WorkspaceApiError>();
+    }
+
+    #[cfg(feature = "mock_client")]
+    #[tokio::test]
+    async fn test_error_crosses_tasks() {
+        let error = tokio::spawn(async { WorkspaceApiError::Transport("connection reset".into()) })
+            .await
+            .unwrap();
+        assert!(matches!(error, WorkspaceApiError::Transport(_)));
+        assert_eq!(error.to_string(), "Transport error: connection reset");
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let path = RelativePath::new("some/path").unwrap();
+        let name = "name".to_string();
+        let errors = [
+            (WorkspaceApiError::NotFound(path.clone()), false),
+            (WorkspaceApiError::NotADirectory(path.clone()), false),
+            (WorkspaceApiError::IsADirectory(path.clone()), false),
+            (WorkspaceApiError::AlreadyExists(path.clone()), false),
+            (WorkspaceApiError::NotOpened(path.clone()), false),
+            (WorkspaceApiError::NotConflicted(path.clone()), false),
+            (
+                WorkspaceApiError::AlreadyOpened(path.clone(), ChangeState::Modified),
+                false,
+            ),
+            (WorkspaceApiError::LockedByOther(path.clone(), name.clone()), false),
+            (WorkspaceApiError::NotLocked(path.clone()), false),
+            (WorkspaceApiError::PendingChanges(vec![path.clone()]), false),
+            (WorkspaceApiError::StreamNotFound(name.clone()), false),
+            (WorkspaceApiError::StreamAlreadyExists(name.clone()), false),
+            (WorkspaceApiError::InvalidStreamType(StreamType::Mainline), false),
+            (WorkspaceApiError::LabelNotFound(name.clone()), false),
+            (WorkspaceApiError::LabelAlreadyExists(name.clone()), false),
+            (WorkspaceApiError::ShelfNotFound(ShelfId::new(1)), false),
+            (WorkspaceApiError::NothingToShelve, false),
+            (WorkspaceApiError::WorkspaceNotFound(name.clone()), false),
+            (WorkspaceApiError::WorkspaceAlreadyExists(name.clone()), false),
+            (WorkspaceApiError::WorkspaceInUse(name), false),
+            (WorkspaceApiError::NothingToSubmit, false),
+            (WorkspaceApiError::OutOfDate(vec![path.clone()]), false),
+            (WorkspaceApiError::UnresolvedConflicts(vec![path.clone()]), false),
+            (WorkspaceApiError::RevisionNotFound(Revision::new(1)), false),
+            (WorkspaceApiError::PermissionDenied(path), false),
+            (WorkspaceApiError::Transport("connection reset".into()), true),
+            (WorkspaceApiError::Timeout, true),
+            (WorkspaceApiError::Cancelled, false),
+            (WorkspaceApiError::Protocol("bad response".into()), false),
+        ];
+        for (error, retryable) in errors {
+            assert_eq!(error.is_retryable(), retryable, "{:?} is misclassified", error);
+        }
+    }
 }
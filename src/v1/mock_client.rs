@@ -1,20 +1,189 @@
 // == Std
-use std::{ops::Range, path::Path, time::Duration};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    ops::{Range, RangeBounds},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 // == Internal crates
 use super::{
-    client::{DirectoryFetchOptions, WorkspaceApi},
-    model::{Directory, DirectoryEntryType},
+    client::{
+        DirectoryFetchOptions, EntryFetchOptions, HistoryOptions, LabelSource, Resolution, RevertOptions,
+        RevisionSelector, SubmitTarget, TreeVersion, WorkspaceApi, WorkspaceApiError,
+    },
+    model::{
+        self, ChangeState, Changelist, Changeset, ChangesetChange, ConflictInfo, ConflictKind, ConflictState,
+        DEFAULT_CHANGELIST, DIFF_CONTEXT_LINES, Directory, DirectoryEntry, DirectoryEntryType, DirectoryPage,
+        FileAction, FileDiff, FileLock, FileMetadata, FileRevision, IncomingChange, Label, Lock, PageCursor,
+        PendingChange, RevertedFile, Revision, Shelf, ShelfId, Stream, StreamType, SyncAction, SyncProgress,
+        WatchEvent, WatchEventKind, WorkspaceSpec,
+    },
 };
 use crate::common::RelativePath;
 // == External crates
+use futures::{StreamExt, future, stream};
+use serde::Deserialize;
 use thiserror::Error;
-use tokio::time::sleep;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::sleep,
+};
+
+/// The user name the mock submits changes as, unless changed with `MockWorkspaceApi::set_user_name`
+const DEFAULT_USER_NAME: &str = "mock_user";
+
+/// The name of the workspace the mock operates on, which initially maps the whole depot
+const DEFAULT_WORKSPACE_NAME: &str = "mock_workspace";
+
+/// The name of the mainline stream the mock workspace starts on
+const DEFAULT_STREAM_NAME: &str = "main";
+
+/// Capacity of the watch event channel, watchers which fall further behind than this will receive an error
+const WATCH_EVENT_CAPACITY: usize = 1024;
 
 pub struct MockWorkspaceApi {
-    full_directory_tree: Directory,
+    state: Mutex<MockState>,
+    /// Shelves, which may be shared with other mock workspaces
+    shelf_store: MockShelfStore,
+    /// Sender for watch events, each watcher subscribes its own receiver
+    watch_events: broadcast::Sender<WatchEvent>,
     /// Simulated latency range for requests, in milliseconds, each request will be delayed by a random number of
     /// milliseconds within this range
     request_latency_range_ms: Range<u32>,
+    /// Simulated latency range for each chunk of a streamed response, in milliseconds
+    chunk_latency_range_ms: Range<u32>,
+    /// The number of requests charged the simulated request latency so far
+    request_count: AtomicU64,
+}
+
+/// The mutable state of the mock workspace
+struct MockState {
+    /// The tree of the depot, including pending changes, before the view of the current workspace is applied.
+    /// The rest of the state is kept by depot path too, apart from local files, and operations map workspace paths to
+    /// depot paths on the way in and back on the way out.
+    full_directory_tree: Directory,
+    /// The changelist of each file with pending changes which is not in the default changelist
+    changelists: HashMap<RelativePath, String>,
+    /// The revision of the current stream the workspace was last synced to as a whole, which falls behind the
+    /// stream's head revision when snapshots of newer revisions are added
+    have_revision: Revision,
+    /// Metadata of simulated local files by workspace path, which are used when files are opened for add or edit
+    local_files: HashMap<RelativePath, FileMetadata>,
+    /// Metadata of the base version of each file in the depot, which files are restored to when reverted
+    base_metadata: HashMap<RelativePath, FileMetadata>,
+    /// Details of each unresolved or resolved conflict, kept in sync with the conflict states in the tree
+    conflicts: HashMap<RelativePath, ConflictInfo>,
+    /// The original path of each file opened for add by a move
+    moved_from: HashMap<RelativePath, RelativePath>,
+    /// Every submitted revision of every file in the current stream, in no particular order
+    history: Vec<FileRevision>,
+    /// The user name changes are submitted as
+    user_name: String,
+    /// Snapshots of the current stream's tree at historical revisions, in no particular order
+    snapshots: Vec<MockSnapshot>,
+    /// Captured content of each version of each file, keyed by path and the metadata of the version
+    file_contents: HashMap<(RelativePath, FileMetadata), Vec<u8>>,
+    /// Every stream in the depot by name, along with its head revision.  The tree, snapshots, history and labels of
+    /// the current stream are held in the fields above and below instead.
+    streams: BTreeMap<String, MockStream>,
+    /// The name of the stream the workspace is on
+    current_stream: String,
+    /// Every label in the current stream by name, along with a tree of the labeled files
+    labels: BTreeMap<String, (Label, Directory)>,
+    /// The definition of every workspace by name
+    workspaces: BTreeMap<String, WorkspaceSpec>,
+    /// The name of the workspace the mock operates on
+    current_workspace: String,
+}
+
+/// A store of shelves on the simulated server, which can be shared between mock workspaces so that a shelf created in
+/// one can be unshelved in another, see `MockWorkspaceApi::set_shelf_store`.  Clones share the same shelves.
+/// Shelved files are kept at their depot paths, so workspaces with different views can share shelves.
+#[derive(Clone, Default)]
+pub struct MockShelfStore(Arc<Mutex<MockShelves>>);
+
+#[derive(Default)]
+struct MockShelves {
+    /// The id of the most recently created shelf
+    last_id: u64,
+    shelves: BTreeMap<ShelfId, Shelf>,
+}
+
+impl MockShelfStore {
+    fn shelves(&self) -> MutexGuard<'_, MockShelves> {
+        self.0.lock().expect("Mock shelf store lock should not be poisoned")
+    }
+}
+
+/// A stream held by the mock, along with its tree, snapshots, history and labels while the workspace is not on it
+struct MockStream {
+    stream: Stream,
+    tree: Directory,
+    snapshots: Vec<MockSnapshot>,
+    history: Vec<FileRevision>,
+    labels: BTreeMap<String, (Label, Directory)>,
+}
+
+/// Captured text content of a version of a file, see `MockWorkspaceApi::set_file_contents_from_json_str`
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockFileContent {
+    pub path: RelativePath,
+    /// The metadata of the version of the file the content belongs to
+    pub metadata: FileMetadata,
+    pub content: String,
+}
+
+/// A named snapshot of the depot tree as it was at a historical revision, see `MockWorkspaceApi::add_snapshot`
+#[derive(Debug, Clone)]
+pub struct MockSnapshot {
+    pub name: String,
+    /// The revision the snapshot was taken at, it is served for every revision until the next snapshot
+    pub revision: Revision,
+    /// The time the revision was submitted, in Unix milliseconds UTC
+    pub submitted_time_unix_ms_utc: u64,
+    /// The depot tree at the revision, which should be fully loaded
+    pub tree: Directory,
+}
+
+/// A scripted change to the mock directory tree, see `MockWorkspaceApi::apply_mutation`
+/// Paths are depot paths, which only differ from workspace paths if the view of the current workspace remaps them.
+#[derive(Debug, Clone)]
+pub enum MockMutation {
+    /// Adds a new file, the parent directory must already exist
+    AddFile {
+        path: RelativePath,
+        metadata: FileMetadata,
+        change_state: ChangeState,
+        conflict_state: ConflictState,
+    },
+    /// Adds a new empty directory, the parent directory must already exist
+    AddDirectory { path: RelativePath },
+    /// Removes a file or directory, including all of its contents
+    RemoveEntry { path: RelativePath },
+    /// Sets the change state of a file
+    SetChangeState {
+        path: RelativePath,
+        change_state: ChangeState,
+    },
+    /// Sets the conflict state of a file
+    SetConflictState {
+        path: RelativePath,
+        conflict_state: ConflictState,
+    },
+    /// Sets the metadata of a file
+    SetMetadata { path: RelativePath, metadata: FileMetadata },
+    /// Adds a conflict to the file at the conflict's path, setting the file's conflict state to match
+    AddConflict(ConflictInfo),
+    /// Sets the owner of the lock on a file, or unlocks the file if `None`
+    /// The lock is `Lock::LockedByYou` if the owner is the mock's user name, otherwise
+    /// `Lock::LockedByOther`, which simulates a lock held by another user.
+    SetLock { path: RelativePath, owner: Option<String> },
 }
 
 #[derive(Debug, Error)]
@@ -33,15 +202,88 @@ impl Default for MockWorkspaceApi {
 
 impl MockWorkspaceApi {
     pub fn new() -> Self {
+        Self::with_directory_tree(Directory::new(RelativePath::new("").unwrap(), vec![]))
+    }
+
+    /// Creates a new MockWorkspaceApi serving the given directory tree, which should be fully loaded
+    pub fn with_directory_tree(directory: Directory) -> Self {
         MockWorkspaceApi {
-            full_directory_tree: Directory::new(RelativePath::new("").unwrap(), vec![]),
+            state: Mutex::new(MockState {
+                base_metadata: base_metadata(&directory),
+                full_directory_tree: directory,
+                changelists: HashMap::new(),
+                have_revision: Revision::new(0),
+                local_files: HashMap::new(),
+                conflicts: HashMap::new(),
+                moved_from: HashMap::new(),
+                history: vec![],
+                user_name: DEFAULT_USER_NAME.to_string(),
+                snapshots: vec![],
+                file_contents: HashMap::new(),
+                streams: BTreeMap::from([(
+                    DEFAULT_STREAM_NAME.to_string(),
+                    MockStream {
+                        stream: Stream {
+                            name: DEFAULT_STREAM_NAME.to_string(),
+                            parent: None,
+                            stream_type: StreamType::Mainline,
+                            head_revision: Revision::new(0),
+                        },
+                        tree: Directory::new(RelativePath::default(), vec![]),
+                        snapshots: vec![],
+                        history: vec![],
+                        labels: BTreeMap::new(),
+                    },
+                )]),
+                current_stream: DEFAULT_STREAM_NAME.to_string(),
+                labels: BTreeMap::new(),
+                workspaces: BTreeMap::from([(
+                    DEFAULT_WORKSPACE_NAME.to_string(),
+                    WorkspaceSpec {
+                        name: DEFAULT_WORKSPACE_NAME.to_string(),
+                        owner: DEFAULT_USER_NAME.to_string(),
+                        root: PathBuf::from(DEFAULT_WORKSPACE_NAME),
+                        view: WorkspaceSpec::full_view(),
+                    },
+                )]),
+                current_workspace: DEFAULT_WORKSPACE_NAME.to_string(),
+            }),
+            shelf_store: MockShelfStore::default(),
+            watch_events: broadcast::Sender::new(WATCH_EVENT_CAPACITY),
             request_latency_range_ms: 0..1,
+            chunk_latency_range_ms: 0..1,
+            request_count: AtomicU64::new(0),
         }
     }
 
+    /// Sets the simulated latency range for requests, in milliseconds
+    pub fn set_request_latency_range_ms(&mut self, request_latency_range_ms: Range<u32>) {
+        self.request_latency_range_ms = request_latency_range_ms;
+    }
+
+    /// Sets the simulated latency range for each chunk of a streamed response, in milliseconds
+    pub fn set_chunk_latency_range_ms(&mut self, chunk_latency_range_ms: Range<u32>) {
+        self.chunk_latency_range_ms = chunk_latency_range_ms;
+    }
+
+    /// Returns the number of requests made so far, each of which is charged the simulated request latency once.
+    /// Chunks of streamed responses are not counted.
+    pub fn request_count(&self) -> u64 {
+        self.request_count.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the directory tree of the current stream, which should be fully loaded, and which the workspace is
+    /// then synced to the head revision of.  No watch events are emitted.
+    pub fn set_directory_tree(&mut self, directory: Directory) {
+        let state = self.state_mut();
+        state.base_metadata = base_metadata(&directory);
+        state.full_directory_tree = directory;
+        state.have_revision = state.head_revision();
+    }
+
     pub async fn set_directory_tree_from_json_str(&mut self, json_data: &str) -> Result<(), MockWorkspaceApiJsonError> {
         let directory: Directory = serde_json::from_str(json_data)?;
-        self.full_directory_tree = directory;
+        self.set_directory_tree(directory);
 
         Ok(())
     }
@@ -54,193 +296,5616 @@ impl MockWorkspaceApi {
         self.set_directory_tree_from_json_str(&json).await
     }
 
-    async fn delay(&self) {
-        let delay_ms = rand::random_range(self.request_latency_range_ms.clone());
-        if delay_ms > 0 {
-            eprintln!("MockWorkspaceApi delaying request by {} ms", delay_ms);
+    /// Sets the user name which changes are submitted as
+    pub fn set_user_name(&mut self, user_name: impl Into<String>) {
+        self.state_mut().user_name = user_name.into();
+    }
+
+    /// Replaces the file history of the current stream, advancing the stream's head revision to the newest revision in
+    /// the history if needed.  The history is taken to be that of the directory tree, so the workspace is synced to
+    /// the head revision.
+    pub fn set_history(&mut self, history: Vec<FileRevision>) {
+        let state = self.state_mut();
+        if let Some(newest_revision) = history.iter().map(|file_revision| file_revision.revision).max() {
+            state.advance_head_revision(newest_revision);
         }
-        sleep(Duration::from_millis(delay_ms as u64)).await;
+        state.history = history;
+        state.have_revision = state.head_revision();
     }
-}
 
-impl WorkspaceApi for MockWorkspaceApi {
-    async fn fetch_directory(
-        &self,
-        path: &RelativePath,
-        options: DirectoryFetchOptions,
-    ) -> Result<Option<Directory>, Box<dyn std::error::Error>> {
-        self.delay().await;
+    pub async fn set_history_from_json_str(&mut self, json_data: &str) -> Result<(), MockWorkspaceApiJsonError> {
+        let history: Vec<FileRevision> = serde_json::from_str(json_data)?;
+        self.set_history(history);
 
-        let mut directory = if path.is_empty() {
-            self.full_directory_tree.clone()
+        Ok(())
+    }
+
+    pub async fn set_history_from_json_file(&mut self, json_file_path: &Path) -> Result<(), MockWorkspaceApiJsonError> {
+        let json = tokio::fs::read_to_string(json_file_path).await?;
+        self.set_history_from_json_str(&json).await
+    }
+
+    /// Adds a snapshot of the current stream to serve for fetches at historical revisions, replacing any existing
+    /// snapshot with the same name.  The stream's head revision is advanced to the snapshot's revision if needed, but
+    /// the workspace stays at its revision until it is synced.  Revisions before the first snapshot are served an empty
+    /// tree.
+    /// Files in the workspace whose base version differs from the head revision are marked as
+    /// `ConflictState::Incoming`, simulating work submitted by others, until they are synced.
+    pub fn add_snapshot(&mut self, snapshot: MockSnapshot) {
+        let state = self.state_mut();
+        state.advance_head_revision(snapshot.revision);
+        state.snapshots.retain(|existing| existing.name != snapshot.name);
+        state.snapshots.push(snapshot);
+        state.mark_incoming();
+    }
+
+    /// Adds a stream with the given tree, which should be fully loaded, replacing any existing stream with the same
+    /// name.  The stream has no snapshots, history or labels until they are added while the workspace is on it.
+    /// The mock starts on a mainline stream named "main", and replacing the current stream only replaces the
+    /// workspace tree.
+    pub fn add_stream(&mut self, stream: Stream, tree: Directory) {
+        let state = self.state_mut();
+        let tree = if stream.name == state.current_stream {
+            state.base_metadata = base_metadata(&tree);
+            state.full_directory_tree = tree;
+            Directory::new(RelativePath::default(), vec![])
         } else {
-            let mut current = &self.full_directory_tree;
-
-            for component in path.components() {
-                // Find the component in the current directory - inefficient but acceptable for a mock
-                let entry_opt = current.entries().iter().find(|entry| entry.name() == component);
-                if let Some(entry) = entry_opt {
-                    match entry.info() {
-                        DirectoryEntryType::Directory(Some(dir_info)) => {
-                            current = dir_info;
-                        }
-                        DirectoryEntryType::Directory(None) => {
-                            // The mock directory tree will not contain any unloaded directories, so this is an error
-                            panic!("Mock directory tree should not contain unloaded directories");
-                        }
-                        DirectoryEntryType::File { .. } => {
-                            // Entry is a file, so the path cannot continue
-                            return Ok(None);
-                        }
+            tree
+        };
+        let mock_stream = MockStream {
+            stream,
+            tree,
+            snapshots: vec![],
+            history: vec![],
+            labels: BTreeMap::new(),
+        };
+        state.streams.insert(mock_stream.stream.name.clone(), mock_stream);
+    }
+
+    /// Returns the store of this mock's shelves, which can be shared with another mock with `set_shelf_store`
+    pub fn shelf_store(&self) -> MockShelfStore {
+        self.shelf_store.clone()
+    }
+
+    /// Replaces the store of this mock's shelves, for example to share shelves with another mock
+    pub fn set_shelf_store(&mut self, shelf_store: MockShelfStore) {
+        self.shelf_store = shelf_store;
+    }
+
+    /// Sets the content of the version of the file at the given depot path with the given metadata.
+    /// Content is matched to a version of a file by its metadata, so the same path can have content for its workspace,
+    /// base and historical versions.
+    pub fn set_file_content(&mut self, path: RelativePath, metadata: FileMetadata, content: impl Into<Vec<u8>>) {
+        self.state_mut().file_contents.insert((path, metadata), content.into());
+    }
+
+    /// Sets the content of each version of a file listed in the JSON, which is an array of MockFileContent
+    pub async fn set_file_contents_from_json_str(&mut self, json_data: &str) -> Result<(), MockWorkspaceApiJsonError> {
+        let file_contents: Vec<MockFileContent> = serde_json::from_str(json_data)?;
+        for file_content in file_contents {
+            self.set_file_content(file_content.path, file_content.metadata, file_content.content);
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_file_contents_from_json_file(
+        &mut self,
+        json_file_path: &Path,
+    ) -> Result<(), MockWorkspaceApiJsonError> {
+        let json = tokio::fs::read_to_string(json_file_path).await?;
+        self.set_file_contents_from_json_str(&json).await
+    }
+
+    /// Sets the metadata of a simulated local file at the given workspace path.
+    /// A local file must be set before a new path can be opened for add, and the metadata of a file opened for edit is
+    /// updated from its local file if one is set.  The local file is consumed when the path is opened.
+    pub fn set_local_file_metadata(&self, path: RelativePath, metadata: FileMetadata) {
+        self.state().local_files.insert(path, metadata);
+    }
+
+    /// Sends a watch event to all matching watchers as it is, without changing the mock directory tree
+    pub fn push_watch_event(&self, event: WatchEvent) {
+        // Sending only fails when there are no watchers, which is fine
+        let _ = self.watch_events.send(event);
+    }
+
+    /// Applies a scripted change to the mock directory tree, keeping the aggregated states of all ancestors correct
+    /// and emitting the matching watch events.  Mutations are applied immediately, without any simulated latency.
+    pub fn apply_mutation(&self, mutation: MockMutation) -> Result<(), WorkspaceApiError> {
+        let events = {
+            let mut state = self.state();
+            match mutation {
+                MockMutation::AddFile {
+                    path,
+                    metadata,
+                    change_state,
+                    conflict_state,
+                } => {
+                    let event = state.insert_entry(
+                        &path,
+                        DirectoryEntryType::File {
+                            metadata: metadata.clone(),
+                            change_state,
+                            conflict_state,
+                            lock: Lock::Unlocked,
+                        },
+                    )?;
+                    if change_state != ChangeState::Added {
+                        state.base_metadata.insert(path, metadata);
+                    }
+                    vec![event]
+                }
+                MockMutation::AddDirectory { path } => {
+                    let directory = Directory::new(path.clone(), vec![]);
+                    vec![state.insert_entry(&path, DirectoryEntryType::Directory(Some(directory)))?]
+                }
+                MockMutation::RemoveEntry { path } => {
+                    let event = state.remove_entry(&path)?;
+                    state.base_metadata.retain(|file_path, _| !file_path.starts_with(&path));
+                    vec![event]
+                }
+                MockMutation::SetChangeState { path, change_state } => {
+                    state.update_file(&path, |_, file_change_state, _| *file_change_state = change_state)?
+                }
+                MockMutation::SetConflictState { path, conflict_state } => {
+                    state.update_file(&path, |_, _, file_conflict_state| *file_conflict_state = conflict_state)?
+                }
+                MockMutation::SetMetadata { path, metadata } => {
+                    let events = state.update_file(&path, |file_metadata, _, _| *file_metadata = metadata.clone())?;
+                    if state.file_change_state(&path)? == ChangeState::Unchanged {
+                        state.base_metadata.insert(path, metadata);
+                    }
+                    events
+                }
+                MockMutation::AddConflict(info) => {
+                    let events = state.update_file(&info.path, |_, _, conflict_state| {
+                        *conflict_state = info.conflict_state;
+                    })?;
+                    if matches!(info.conflict_state, ConflictState::Unresolved | ConflictState::Resolved) {
+                        state.conflicts.insert(info.path.clone(), info);
                     }
-                } else {
-                    // Component not found
-                    return Ok(None);
+                    events
                 }
+                MockMutation::SetLock { path, owner } => state.set_lock(&path, owner)?,
             }
+        };
+
+        self.push_watch_events(events);
+        Ok(())
+    }
 
-            current.clone()
+    /// Sends the watch events for changes at depot paths to all matching watchers, at the workspace paths the changes
+    /// are mapped to.  Events for changes outside the view are dropped.
+    fn push_watch_events(&self, events: Vec<WatchEvent>) {
+        let events = {
+            let state = self.state();
+            events
+                .into_iter()
+                .filter_map(|event| state.workspace_event(event))
+                .collect::<Vec<_>>()
         };
+        for event in events {
+            self.push_watch_event(event);
+        }
+    }
+
+    /// Runs an operation on the file at the given workspace path, which the operation addresses by its depot path,
+    /// then sends the resulting watch events and returns the entry named as it is in the workspace
+    fn on_file(
+        &self,
+        path: &RelativePath,
+        operation: impl FnOnce(
+            &mut MockState,
+            &RelativePath,
+        ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError>,
+    ) -> Result<DirectoryEntry, WorkspaceApiError> {
+        let (entry, events) = {
+            let mut state = self.state();
+            let depot_path = state.depot_path(path)?;
+            let (entry, events) = state.on_depot(|state| operation(state, &depot_path))?;
+            (state.workspace_entry(&depot_path, entry), events)
+        };
+        self.push_watch_events(events);
+
+        Ok(entry)
+    }
 
-        if let Some(depth_limit) = options.depth_limit {
-            // Cull entries beyond the depth limit
-            directory.prune_to_depth(depth_limit);
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("Mock state lock should not be poisoned")
+    }
+
+    fn state_mut(&mut self) -> &mut MockState {
+        self.state.get_mut().expect("Mock state lock should not be poisoned")
+    }
+
+    async fn delay(&self) {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        let delay_ms = rand::random_range(self.request_latency_range_ms.clone());
+        if delay_ms > 0 {
+            eprintln!("MockWorkspaceApi delaying request by {} ms", delay_ms);
         }
+        sleep(Duration::from_millis(delay_ms as u64)).await;
+    }
 
-        Ok(Some(directory))
+    async fn chunk_delay(&self) {
+        // Not logged, as streamed responses have many chunks
+        let delay_ms = rand::random_range(self.chunk_latency_range_ms.clone());
+        sleep(Duration::from_millis(delay_ms as u64)).await;
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::v1::model::*;
+impl MockState {
+    /// Returns the tree for the given revision with the view of the current workspace applied, or the workspace tree
+    /// if no revision is selected
+    fn tree(&self, revision: Option<&RevisionSelector>) -> Result<Cow<'_, Directory>, WorkspaceApiError> {
+        match revision {
+            None => Ok(self.workspace_tree()),
+            Some(revision) => Ok(self.apply_view(self.revision_tree(revision)?)),
+        }
+    }
 
-    #[tokio::test]
-    async fn test_fetch_directory() {
-        let mut root = Directory::new(RelativePath::new("").unwrap(), vec![]);
+    /// Returns the tree of the depot for the given revision, before the view of the current workspace is applied
+    fn revision_tree(&self, revision: &RevisionSelector) -> Result<Cow<'_, Directory>, WorkspaceApiError> {
+        let revision = match revision {
+            RevisionSelector::Label(name) => {
+                return self
+                    .labels
+                    .get(name)
+                    .map(|(_, tree)| Cow::Borrowed(tree))
+                    .ok_or_else(|| WorkspaceApiError::LabelNotFound(name.clone()));
+            }
+            selector => self.resolve_revision(selector)?,
+        };
+        let snapshot = self
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.revision <= revision)
+            .max_by_key(|snapshot| snapshot.revision);
 
-        let mut sub_dir = Directory::new(RelativePath::new("subdir").unwrap(), vec![]);
+        Ok(match snapshot {
+            Some(snapshot) => Cow::Borrowed(&snapshot.tree),
+            None => Cow::Owned(Directory::new(RelativePath::default(), vec![])),
+        })
+    }
 
-        let mut sub_sub_dir = Directory::new(RelativePath::new("subdir/nested").unwrap(), vec![]);
+    /// Returns a tree of the pending changes in the named changelist, for shelving
+    fn shelf_contents(&self, changelist: &str) -> Result<Directory, WorkspaceApiError> {
+        let files = self
+            .pending_changes(&RelativePath::default())
+            .into_iter()
+            .filter(|change| self.changelist_of(&change.path) == changelist)
+            .map(|change| {
+                let name = change
+                    .path
+                    .file_name()
+                    .expect("File path should have a file name")
+                    .to_string();
+                let entry = DirectoryEntry::new(
+                    name,
+                    DirectoryEntryType::File {
+                        metadata: change.metadata,
+                        change_state: change.change_state,
+                        conflict_state: ConflictState::None,
+                        lock: Lock::Unlocked,
+                    },
+                );
+                (change.path, entry)
+            })
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return Err(WorkspaceApiError::NothingToShelve);
+        }
 
-        sub_sub_dir.push_entry(DirectoryEntry::new(
-            "file.txt".into(),
-            DirectoryEntryType::File {
-                metadata: FileMetadata::new(0, 0),
-                change_state: Default::default(),
-                conflict_state: Default::default(),
-            },
-        ));
+        Ok(files_tree(files))
+    }
 
-        sub_dir.push_entry(DirectoryEntry::new(
-            "nested".into(),
-            DirectoryEntryType::Directory(Some(sub_sub_dir)),
-        ));
+    /// Opens the changes in the shelf contents in the named changelist, returning the unshelved changes and the
+    /// resulting watch events.  Shelves hold files at their depot paths, so every file must be in the view.
+    fn unshelve(
+        &mut self,
+        contents: &Directory,
+        changelist: &str,
+    ) -> Result<(Vec<PendingChange>, Vec<WatchEvent>), WorkspaceApiError> {
+        let mut changes = contents
+            .files()
+            .filter_map(|(path, entry)| match entry.info() {
+                DirectoryEntryType::File {
+                    metadata, change_state, ..
+                } => Some(PendingChange {
+                    path,
+                    change_state: *change_state,
+                    metadata: metadata.clone(),
+                }),
+                DirectoryEntryType::Directory(_) => None,
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
 
-        root.push_entry(DirectoryEntry::new(
-            "subdir".into(),
-            DirectoryEntryType::Directory(Some(sub_dir)),
-        ));
+        // Check every file up front, so nothing is unshelved if any file can't be
+        for change in &changes {
+            if self.workspace_path(&change.path).is_none() {
+                return Err(WorkspaceApiError::NotFound(change.path.clone()));
+            }
+            if change.change_state == ChangeState::Added {
+                self.check_vacant(&change.path)?;
+                continue;
+            }
+            match self.file_change_state(&change.path)? {
+                ChangeState::Unchanged => self.check_not_locked_by_other(&change.path)?,
+                change_state => return Err(WorkspaceApiError::AlreadyOpened(change.path.clone(), change_state)),
+            }
+        }
 
-        //println!("Constructed mock directory tree: {}", serde_json::to_string_pretty(&root).unwrap());
+        let mut events = vec![];
+        for change in &changes {
+            if change.change_state == ChangeState::Added {
+                let parent_path = change.path.parent().expect("Vacant path should not be the root path");
+                events.extend(self.create_directories(&parent_path)?);
+                events.push(self.insert_entry(
+                    &change.path,
+                    DirectoryEntryType::File {
+                        metadata: change.metadata.clone(),
+                        change_state: ChangeState::Added,
+                        conflict_state: ConflictState::None,
+                        lock: Lock::Unlocked,
+                    },
+                )?);
+            } else {
+                events.extend(self.update_file(&change.path, |metadata, change_state, _| {
+                    *metadata = change.metadata.clone();
+                    *change_state = change.change_state;
+                })?);
+            }
+            if changelist != DEFAULT_CHANGELIST {
+                self.changelists.insert(change.path.clone(), changelist.to_string());
+            }
+        }
 
-        let mock_api = MockWorkspaceApi {
-            full_directory_tree: root,
-            request_latency_range_ms: 0..1,
+        Ok((changes, events))
+    }
+
+    /// Returns the revision of the depot selected by the given selector
+    fn resolve_revision(&self, revision: &RevisionSelector) -> Result<Revision, WorkspaceApiError> {
+        match revision {
+            RevisionSelector::Revision(revision) if *revision > self.head_revision() => {
+                Err(WorkspaceApiError::RevisionNotFound(*revision))
+            }
+            RevisionSelector::Revision(revision) => Ok(*revision),
+            RevisionSelector::Timestamp(time_unix_ms_utc) => {
+                let snapshot_revisions = self
+                    .snapshots
+                    .iter()
+                    .map(|snapshot| (snapshot.revision, snapshot.submitted_time_unix_ms_utc));
+                let history_revisions = self
+                    .history
+                    .iter()
+                    .map(|file_revision| (file_revision.revision, file_revision.submitted_time_unix_ms_utc));
+                Ok(snapshot_revisions
+                    .chain(history_revisions)
+                    .filter(|(_, submitted_time_unix_ms_utc)| submitted_time_unix_ms_utc <= time_unix_ms_utc)
+                    .map(|(revision, _)| revision)
+                    .max()
+                    .unwrap_or(Revision::new(0)))
+            }
+            RevisionSelector::Label(name) => self
+                .labels
+                .get(name)
+                .map(|(label, _)| label.revision)
+                .ok_or_else(|| WorkspaceApiError::LabelNotFound(name.clone())),
+        }
+    }
+
+    /// Creates a label tagging the file revisions of the source at or below the scope path
+    fn create_label(
+        &mut self,
+        name: &str,
+        source: &LabelSource,
+        scope: &RelativePath,
+        description: &str,
+    ) -> Result<Label, WorkspaceApiError> {
+        if self.labels.contains_key(name) {
+            return Err(WorkspaceApiError::LabelAlreadyExists(name.to_string()));
+        }
+
+        let (revision, tree) = match source {
+            LabelSource::Revision(revision) => (self.resolve_revision(revision)?, self.revision_tree(revision)?),
+            LabelSource::Workspace => (self.have_revision, Cow::Owned(self.base_tree())),
         };
+        if !scope.is_empty() {
+            find_entry(&self.apply_view(Cow::Borrowed(&tree)), scope)?;
+        }
+        // Labels keep the files at their depot paths, like snapshots, so the view is applied when they are fetched
+        let files = tree
+            .files()
+            .filter(|(path, _)| self.in_scope(path, scope))
+            .map(|(path, entry)| (path, entry.clone()))
+            .collect();
+        let tree = depot_tree(&files_tree(files));
+        let label = Label {
+            name: name.to_string(),
+            description: description.to_string(),
+            owner: self.user_name.clone(),
+            created_time_unix_ms_utc: now_unix_ms(),
+            scope: scope.clone(),
+            revision,
+        };
+        self.labels.insert(name.to_string(), (label.clone(), tree));
 
-        let fetch_options = DirectoryFetchOptions::default();
+        Ok(label)
+    }
 
-        let dir = mock_api
-            .fetch_directory(&RelativePath::new("missing/path").unwrap(), fetch_options.clone())
-            .await
-            .unwrap();
-        assert!(dir.is_none());
+    /// Returns the most recently submitted revision of the current stream
+    fn head_revision(&self) -> Revision {
+        self.current_stream().head_revision
+    }
 
-        let dir = mock_api
-            .fetch_directory(&RelativePath::new("subdir").unwrap(), fetch_options.clone())
-            .await
-            .unwrap()
-            .expect("subdir should exist");
-        assert_eq!(dir.relative_path().to_string(), "subdir");
+    /// Advances the head revision of the current stream to the given revision, if it is newer
+    fn advance_head_revision(&mut self, revision: Revision) {
+        let current = self
+            .streams
+            .get_mut(&self.current_stream)
+            .expect("Current stream should exist");
+        current.stream.head_revision = current.stream.head_revision.max(revision);
+    }
 
-        let dir = mock_api
-            .fetch_directory(&RelativePath::new("subdir/nested").unwrap(), fetch_options.clone())
-            .await
-            .unwrap()
-            .expect("subdir/nested should exist");
-        assert_eq!(dir.relative_path().to_string(), "subdir/nested");
+    /// Returns the stream the workspace is on
+    fn current_stream(&self) -> &Stream {
+        &self.streams[&self.current_stream].stream
+    }
 
-        let dir = mock_api
-            .fetch_directory(
-                &RelativePath::new("subdir/nested/file.txt").unwrap(),
-                fetch_options.clone(),
+    /// Creates a new stream branched from the named parent stream, which starts with the parent's snapshots and
+    /// history but no labels
+    fn create_stream(
+        &mut self,
+        name: &str,
+        parent: &str,
+        stream_type: StreamType,
+    ) -> Result<Stream, WorkspaceApiError> {
+        if stream_type == StreamType::Mainline {
+            return Err(WorkspaceApiError::InvalidStreamType(stream_type));
+        }
+        if self.streams.contains_key(name) {
+            return Err(WorkspaceApiError::StreamAlreadyExists(name.to_string()));
+        }
+        let Some(parent_stream) = self.streams.get(parent) else {
+            return Err(WorkspaceApiError::StreamNotFound(parent.to_string()));
+        };
+
+        // The workspace's pending changes are not part of the current stream until they are submitted
+        let (tree, snapshots, history) = if parent == self.current_stream {
+            (
+                depot_tree(&self.base_tree()),
+                self.snapshots.clone(),
+                self.history.clone(),
             )
-            .await
-            .unwrap();
-        assert!(dir.is_none());
+        } else {
+            (
+                depot_tree(&parent_stream.tree),
+                parent_stream.snapshots.clone(),
+                parent_stream.history.clone(),
+            )
+        };
+        let stream = Stream {
+            name: name.to_string(),
+            parent: Some(parent.to_string()),
+            stream_type,
+            head_revision: parent_stream.stream.head_revision,
+        };
+        self.streams.insert(
+            name.to_string(),
+            MockStream {
+                stream: stream.clone(),
+                tree,
+                snapshots,
+                history,
+                labels: BTreeMap::new(),
+            },
+        );
+
+        Ok(stream)
     }
 
-    #[tokio::test]
-    async fn test_json_data() {
-        let test_json_data = include_str!("test_data/lyra.json");
-        let mut mock_api = MockWorkspaceApi::default();
+    /// Switches the workspace to the named stream, swapping the workspace tree, snapshots, history and labels for
+    /// those of that stream
+    fn switch_stream(&mut self, name: &str) -> Result<Stream, WorkspaceApiError> {
+        if !self.streams.contains_key(name) {
+            return Err(WorkspaceApiError::StreamNotFound(name.to_string()));
+        }
+        if name == self.current_stream {
+            return Ok(self.current_stream().clone());
+        }
+        let pending_changes = self.pending_changes(&RelativePath::default());
+        if !pending_changes.is_empty() {
+            return Err(WorkspaceApiError::PendingChanges(
+                pending_changes
+                    .into_iter()
+                    .map(|change| self.workspace_change(change).path)
+                    .collect(),
+            ));
+        }
 
-        let result = mock_api
-            .fetch_directory(&RelativePath::new("").unwrap(), DirectoryFetchOptions::default())
-            .await
-            .unwrap();
-        assert!(
-            result.unwrap().entries().is_empty(),
-            "Initial mock directory should be empty"
-        );
+        let target = self.streams.get_mut(name).expect("Stream should exist");
+        let target_tree = std::mem::replace(&mut target.tree, Directory::new(RelativePath::default(), vec![]));
+        let target_snapshots = std::mem::take(&mut target.snapshots);
+        let target_history = std::mem::take(&mut target.history);
+        let target_labels = std::mem::take(&mut target.labels);
+        let previous = self
+            .streams
+            .get_mut(&self.current_stream)
+            .expect("Current stream should exist");
+        previous.tree = std::mem::replace(&mut self.full_directory_tree, target_tree);
+        previous.snapshots = std::mem::replace(&mut self.snapshots, target_snapshots);
+        previous.history = std::mem::replace(&mut self.history, target_history);
+        previous.labels = std::mem::replace(&mut self.labels, target_labels);
+        self.base_metadata = base_metadata(&self.full_directory_tree);
+        self.current_stream = name.to_string();
+        self.have_revision = self.head_revision();
 
-        mock_api
-            .set_directory_tree_from_json_str(test_json_data)
-            .await
-            .expect("Setting directory tree from JSON should succeed");
+        Ok(self.current_stream().clone())
+    }
 
-        let result = mock_api
-            .fetch_directory(&RelativePath::new("").unwrap(), DirectoryFetchOptions::default())
-            .await
-            .unwrap()
-            .unwrap();
+    /// Returns the definition of the workspace the mock operates on
+    fn workspace_spec(&self) -> &WorkspaceSpec {
+        &self.workspaces[&self.current_workspace]
+    }
 
-        assert!(
-            !result.entries().is_empty(),
-            "Mock directory should not be empty after setting JSON data"
-        );
+    /// Returns the tree of the depot with the view of the current workspace applied
+    fn workspace_tree(&self) -> Cow<'_, Directory> {
+        self.apply_view(Cow::Borrowed(&self.full_directory_tree))
+    }
 
-        // No pruning means first entry should be "Build"
-        let first_entry = &result.entries()[0];
-        assert_eq!(first_entry.name(), "Build", "First entry should be 'Build'");
+    /// Applies the view of the current workspace to a tree of the depot, keeping only the mapped files at their
+    /// workspace paths
+    fn apply_view<'a>(&self, tree: Cow<'a, Directory>) -> Cow<'a, Directory> {
+        let spec = self.workspace_spec();
+        if spec.view == WorkspaceSpec::full_view() {
+            return tree;
+        }
 
-        // Directory should be loaded
-        assert!(
-            matches!(first_entry.info(), DirectoryEntryType::Directory(Some(_))),
-            "First entry should be a loaded directory"
-        );
+        let files = tree
+            .files()
+            .filter_map(|(depot_path, entry)| {
+                let workspace_path = spec.map_depot_path(&depot_path)?;
+                let name = workspace_path.file_name()?.to_string();
+                Some((workspace_path, DirectoryEntry::new(name, entry.info().clone())))
+            })
+            .collect();
+        Cow::Owned(files_tree(files))
+    }
 
-        // Test depth limiting
-        let result = mock_api
-            .fetch_directory(
-                &RelativePath::new("").unwrap(),
-                DirectoryFetchOptions {
-                    depth_limit: Some(0),
-                    filter_string: None,
-                },
-            )
-            .await
-            .unwrap()
-            .unwrap();
+    /// Returns the depot path the given workspace path is mapped from, or `WorkspaceApiError::NotFound` if the path is
+    /// outside the view of the current workspace
+    fn depot_path(&self, path: &RelativePath) -> Result<RelativePath, WorkspaceApiError> {
+        self.workspace_spec()
+            .map_workspace_path(path)
+            .ok_or_else(|| WorkspaceApiError::NotFound(path.clone()))
+    }
 
-        // Pruning means first entry should still be "Build", but unloaded
-        let first_entry = &result.entries()[0];
-        assert_eq!(first_entry.name(), "Build", "First entry should be 'Build'");
-        assert!(
-            matches!(first_entry.info(), DirectoryEntryType::Directory(None)),
-            "First entry should be an unloaded directory due to depth limit"
-        );
+    /// Returns the workspace path the given depot path is mapped to, or `None` if it is outside the view of the current
+    /// workspace
+    fn workspace_path(&self, depot_path: &RelativePath) -> Option<RelativePath> {
+        self.workspace_spec().map_depot_path(depot_path)
+    }
+
+    /// Returns whether the given depot path is mapped to a workspace path at or below the scope path
+    fn in_scope(&self, depot_path: &RelativePath, scope: &RelativePath) -> bool {
+        self.workspace_path(depot_path)
+            .is_some_and(|path| path.starts_with(scope))
+    }
+
+    /// Checks that the given scope path exists in the workspace
+    fn check_scope(&self, scope: &RelativePath) -> Result<(), WorkspaceApiError> {
+        if !scope.is_empty() {
+            find_entry(&self.workspace_tree(), scope)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the entry at the given depot path named as it is in the workspace
+    fn workspace_entry(&self, depot_path: &RelativePath, entry: DirectoryEntry) -> DirectoryEntry {
+        match self
+            .workspace_path(depot_path)
+            .as_ref()
+            .and_then(RelativePath::file_name)
+        {
+            Some(name) => DirectoryEntry::new(name.to_string(), entry.info().clone()),
+            None => entry,
+        }
+    }
+
+    /// Returns the pending change to a file at a depot path with the workspace path of the file instead
+    fn workspace_change(&self, change: PendingChange) -> PendingChange {
+        PendingChange {
+            path: self
+                .workspace_path(&change.path)
+                .expect("Pending changes should be in the view"),
+            ..change
+        }
+    }
+
+    /// Returns the submitted revision of a file as it appears in the workspace, or `None` if the file was outside the
+    /// view.  A move from outside the view appears as an add.
+    fn workspace_revision(&self, file_revision: &FileRevision) -> Option<FileRevision> {
+        let action = match &file_revision.action {
+            FileAction::Move { from } => match self.workspace_path(from) {
+                Some(from) => FileAction::Move { from },
+                None => FileAction::Add,
+            },
+            action => action.clone(),
+        };
+        Some(FileRevision {
+            path: self.workspace_path(&file_revision.path)?,
+            action,
+            ..file_revision.clone()
+        })
+    }
+
+    /// Returns the watch event for a change at a depot path as it appears in the workspace, or `None` if the path is
+    /// outside the view
+    fn workspace_event(&self, event: WatchEvent) -> Option<WatchEvent> {
+        let path = self.workspace_path(&event.path)?;
+        let name = path.file_name().unwrap_or_default().to_string();
+        Some(WatchEvent {
+            kind: event.kind,
+            entry: DirectoryEntry::new(name, event.entry.info().clone()),
+            path,
+        })
+    }
+
+    /// Maps the depot paths in an error from an operation on the depot to their workspace paths, leaving any outside
+    /// the view as they are
+    fn workspace_error(&self, error: WorkspaceApiError) -> WorkspaceApiError {
+        let map = |path: RelativePath| self.workspace_path(&path).unwrap_or(path);
+        let map_all = |paths: Vec<RelativePath>| paths.into_iter().map(map).collect();
+        match error {
+            WorkspaceApiError::NotFound(path) => WorkspaceApiError::NotFound(map(path)),
+            WorkspaceApiError::NotADirectory(path) => WorkspaceApiError::NotADirectory(map(path)),
+            WorkspaceApiError::IsADirectory(path) => WorkspaceApiError::IsADirectory(map(path)),
+            WorkspaceApiError::AlreadyExists(path) => WorkspaceApiError::AlreadyExists(map(path)),
+            WorkspaceApiError::NotOpened(path) => WorkspaceApiError::NotOpened(map(path)),
+            WorkspaceApiError::NotConflicted(path) => WorkspaceApiError::NotConflicted(map(path)),
+            WorkspaceApiError::AlreadyOpened(path, change_state) => {
+                WorkspaceApiError::AlreadyOpened(map(path), change_state)
+            }
+            WorkspaceApiError::LockedByOther(path, owner) => WorkspaceApiError::LockedByOther(map(path), owner),
+            WorkspaceApiError::NotLocked(path) => WorkspaceApiError::NotLocked(map(path)),
+            WorkspaceApiError::PendingChanges(paths) => WorkspaceApiError::PendingChanges(map_all(paths)),
+            WorkspaceApiError::OutOfDate(paths) => WorkspaceApiError::OutOfDate(map_all(paths)),
+            WorkspaceApiError::UnresolvedConflicts(paths) => WorkspaceApiError::UnresolvedConflicts(map_all(paths)),
+            WorkspaceApiError::PermissionDenied(path) => WorkspaceApiError::PermissionDenied(map(path)),
+            error => error,
+        }
+    }
+
+    /// Runs an operation which addresses files by their depot paths, mapping the paths in any error it returns back to
+    /// workspace paths
+    fn on_depot<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, WorkspaceApiError>,
+    ) -> Result<T, WorkspaceApiError> {
+        operation(self).map_err(|error| self.workspace_error(error))
+    }
+
+    /// Returns the tree of the depot as the workspace last synced it, without any pending changes
+    fn base_tree(&self) -> Directory {
+        let mut tree = retain_files(&self.full_directory_tree, |change_state| {
+            change_state != ChangeState::Added
+        });
+        for (path, base_metadata) in &self.base_metadata {
+            let (Some(parent_path), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            tree.update_directory(&parent_path, |parent| {
+                if let Some(DirectoryEntryType::File { metadata, .. }) =
+                    parent.entry_mut(name).map(DirectoryEntry::info_mut)
+                {
+                    *metadata = base_metadata.clone();
+                }
+            });
+        }
+        tree
+    }
+
+    /// Returns the tree for the given version, with the view of the current workspace applied
+    fn version_tree(&self, version: &TreeVersion) -> Result<Cow<'_, Directory>, WorkspaceApiError> {
+        match version {
+            TreeVersion::Workspace => {
+                let tree = retain_files(&self.full_directory_tree, |change_state| {
+                    change_state != ChangeState::Deleted
+                });
+                Ok(self.apply_view(Cow::Owned(tree)))
+            }
+            TreeVersion::Base => Ok(self.apply_view(Cow::Owned(self.base_tree()))),
+            TreeVersion::Revision(revision) => self.tree(Some(revision)),
+        }
+    }
+
+    /// Diffs the content of the file at the given path between two versions of the tree.  A version of the file whose
+    /// content was never captured is reported as `WorkspaceApiError::NotFound`, as if the content wasn't available.
+    fn diff_file(
+        &self,
+        path: &RelativePath,
+        from: &TreeVersion,
+        to: &TreeVersion,
+    ) -> Result<FileDiff, WorkspaceApiError> {
+        // Content is captured by depot path, while the trees are looked up by workspace path
+        let depot_path = self.depot_path(path)?;
+        let file_content = |version| -> Result<Option<&[u8]>, WorkspaceApiError> {
+            let tree = self.version_tree(version)?;
+            let metadata = match find_entry(&tree, path) {
+                Ok(entry) => match entry.info() {
+                    DirectoryEntryType::File { metadata, .. } => metadata.clone(),
+                    DirectoryEntryType::Directory(_) => return Err(WorkspaceApiError::IsADirectory(path.clone())),
+                },
+                Err(WorkspaceApiError::NotFound(_)) => return Ok(None),
+                Err(error) => return Err(error),
+            };
+
+            let content = self
+                .file_contents
+                .get(&(depot_path.clone(), metadata))
+                .ok_or_else(|| WorkspaceApiError::NotFound(path.clone()))?;
+            Ok(Some(content.as_slice()))
+        };
+
+        match (file_content(from)?, file_content(to)?) {
+            (None, None) => Err(WorkspaceApiError::NotFound(path.clone())),
+            (from_content, to_content) => Ok(model::diff_file_contents(
+                path.clone(),
+                from_content,
+                to_content,
+                DIFF_CONTEXT_LINES,
+            )),
+        }
+    }
+
+    /// Returns the revision a sync to the target would sync to, along with the changes it would make to the files at
+    /// or below the scope path, ordered by workspace path.
+    ///
+    /// Incoming changes are found by comparing the base metadata of each file in the workspace view with the target
+    /// tree, so without any snapshots there is never anything to sync to the head revision.  Files which already have
+    /// unresolved conflicts are skipped until they are resolved.
+    fn incoming_changes(
+        &self,
+        scope: &RelativePath,
+        target: Option<&RevisionSelector>,
+    ) -> Result<(Revision, Vec<IncomingChange>), WorkspaceApiError> {
+        let head = RevisionSelector::Revision(self.head_revision());
+        let target = match target {
+            None if self.snapshots.is_empty() => return Ok((self.head_revision(), vec![])),
+            None => &head,
+            Some(target) => target,
+        };
+        let revision = self.resolve_revision(target)?;
+        let target_tree = self.revision_tree(target)?;
+
+        let contains_scope = |tree: &Directory| match find_entry(tree, scope) {
+            Ok(_) => Ok(true),
+            Err(WorkspaceApiError::NotFound(_)) => Ok(false),
+            Err(error) => Err(error),
+        };
+        if !scope.is_empty()
+            && !contains_scope(&self.workspace_tree())?
+            && !contains_scope(&self.apply_view(Cow::Borrowed(&target_tree)))?
+        {
+            return Err(WorkspaceApiError::NotFound(scope.clone()));
+        }
+
+        let target_metadata = target_tree
+            .files()
+            .filter(|(path, _)| self.in_scope(path, scope))
+            .filter_map(|(path, entry)| match entry.info() {
+                DirectoryEntryType::File { metadata, .. } => Some((path, metadata.clone())),
+                DirectoryEntryType::Directory(_) => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let paths = self
+            .base_metadata
+            .keys()
+            .filter(|path| self.in_scope(path, scope))
+            .chain(target_metadata.keys())
+            .collect::<BTreeSet<_>>();
+
+        let mut changes = vec![];
+        for path in paths {
+            let base = self.base_metadata.get(path);
+            let theirs = target_metadata.get(path);
+            if base == theirs {
+                continue;
+            }
+
+            let local_states = match find_entry(&self.full_directory_tree, path).map(DirectoryEntry::info) {
+                Ok(DirectoryEntryType::File {
+                    change_state,
+                    conflict_state,
+                    ..
+                }) => Some((*change_state, *conflict_state)),
+                Ok(DirectoryEntryType::Directory(_)) | Err(WorkspaceApiError::NotFound(_)) => None,
+                Err(error) => return Err(error),
+            };
+            let action = match (local_states, base, theirs) {
+                (Some((_, ConflictState::Unresolved)), _, _) => continue,
+                (Some((change_state, _)), _, _) if change_state != ChangeState::Unchanged => SyncAction::Conflict,
+                (_, None, _) => SyncAction::Add,
+                (_, Some(_), Some(_)) => SyncAction::Update,
+                (_, Some(_), None) => SyncAction::Delete,
+            };
+            changes.push(IncomingChange {
+                path: self.workspace_path(path).expect("Path in scope should be in the view"),
+                action,
+                metadata: theirs.cloned(),
+            });
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok((revision, changes))
+    }
+
+    /// Makes a single change of a sync to the given revision to the file at the given depot path, returning the
+    /// resulting watch events
+    fn sync_change(
+        &mut self,
+        path: &RelativePath,
+        change: &IncomingChange,
+        revision: Revision,
+    ) -> Result<Vec<WatchEvent>, WorkspaceApiError> {
+        let events = match (change.action, &change.metadata) {
+            // A file synced to a revision behind the head revision may still have incoming changes
+            (SyncAction::Add, Some(metadata)) => {
+                let parent_path = path.parent().expect("File path should not be the root path");
+                let mut events = self.create_directories(&parent_path)?;
+                events.push(self.insert_entry(
+                    path,
+                    DirectoryEntryType::File {
+                        metadata: metadata.clone(),
+                        change_state: ChangeState::Unchanged,
+                        conflict_state: self.incoming_state(path, Some(metadata)),
+                        lock: Lock::Unlocked,
+                    },
+                )?);
+                events
+            }
+            (SyncAction::Update, Some(metadata)) => {
+                let incoming_state = self.incoming_state(path, Some(metadata));
+                self.update_file(path, |file_metadata, _, conflict_state| {
+                    *file_metadata = metadata.clone();
+                    if matches!(conflict_state, ConflictState::None | ConflictState::Incoming) {
+                        *conflict_state = incoming_state;
+                    }
+                })?
+            }
+            (SyncAction::Delete, _) => vec![self.remove_entry(path)?],
+            (SyncAction::Conflict, theirs_metadata) => {
+                let info = self.incoming_conflict(path, theirs_metadata.clone(), revision)?;
+                let events = self.update_file(path, |_, _, conflict_state| {
+                    *conflict_state = ConflictState::Unresolved;
+                })?;
+                self.conflicts.insert(path.clone(), info);
+                events
+            }
+            (SyncAction::Add | SyncAction::Update, None) => {
+                return Err(WorkspaceApiError::Protocol(format!(
+                    "Incoming change to '{}' has no metadata",
+                    path
+                )));
+            }
+        };
+
+        // The synced version becomes the base version, including for conflicts, which are then resolved against it
+        match &change.metadata {
+            Some(metadata) => self.base_metadata.insert(path.clone(), metadata.clone()),
+            None => self.base_metadata.remove(path),
+        };
+
+        Ok(events)
+    }
+
+    /// Returns `ConflictState::Incoming` if the file at the given depot path differs at the head revision from the
+    /// given base version, otherwise `ConflictState::None`.  Without any snapshots every file is at the head revision.
+    fn incoming_state(&self, path: &RelativePath, base_metadata: Option<&FileMetadata>) -> ConflictState {
+        if self.snapshots.is_empty() {
+            return ConflictState::None;
+        }
+        let Ok(head_tree) = self.revision_tree(&RevisionSelector::Revision(self.head_revision())) else {
+            return ConflictState::None;
+        };
+        let head_metadata = match find_entry(&head_tree, path).map(DirectoryEntry::info) {
+            Ok(DirectoryEntryType::File { metadata, .. }) => Some(metadata),
+            _ => None,
+        };
+        if head_metadata == base_metadata {
+            ConflictState::None
+        } else {
+            ConflictState::Incoming
+        }
+    }
+
+    /// Marks every file without conflicts which differs at the head revision from its base version as
+    /// `ConflictState::Incoming`, and clears the mark from every file which no longer does
+    fn mark_incoming(&mut self) {
+        let incoming_states = self
+            .full_directory_tree
+            .files()
+            .map(|(path, _)| {
+                let incoming_state = self.incoming_state(&path, self.base_metadata.get(&path));
+                (path, incoming_state)
+            })
+            .collect::<Vec<_>>();
+        for (path, incoming_state) in incoming_states {
+            // Nothing can be watching while the mock is being set up, so the events are not needed
+            let _ = self.update_file(&path, |_, _, conflict_state| {
+                if matches!(conflict_state, ConflictState::None | ConflictState::Incoming) {
+                    *conflict_state = incoming_state;
+                }
+            });
+        }
+    }
+
+    /// Returns the details of the conflict between the pending changes to a file and an incoming change from a sync to
+    /// the given revision, which are taken from the file's history where it is available
+    fn incoming_conflict(
+        &self,
+        path: &RelativePath,
+        theirs_metadata: Option<FileMetadata>,
+        revision: Revision,
+    ) -> Result<ConflictInfo, WorkspaceApiError> {
+        let latest_revision = |before: Revision| {
+            self.history
+                .iter()
+                .filter(|file_revision| file_revision.path == *path && file_revision.revision <= before)
+                .max_by_key(|file_revision| file_revision.revision)
+        };
+        let theirs = latest_revision(revision);
+        let theirs_revision = theirs.map_or(revision, |file_revision| file_revision.revision);
+        let base_revision = theirs_revision
+            .number()
+            .checked_sub(1)
+            .and_then(|before| latest_revision(Revision::new(before)))
+            .map_or(Revision::new(0), |file_revision| file_revision.revision);
+        let published_time_unix_ms_utc = match theirs {
+            Some(file_revision) => file_revision.submitted_time_unix_ms_utc,
+            None => self
+                .snapshots
+                .iter()
+                .filter(|snapshot| snapshot.revision <= revision)
+                .max_by_key(|snapshot| snapshot.revision)
+                .map_or(0, |snapshot| snapshot.submitted_time_unix_ms_utc),
+        };
+
+        let kind = if theirs_metadata.is_none() || self.file_change_state(path)? == ChangeState::Deleted {
+            ConflictKind::DeleteVsEdit
+        } else {
+            ConflictKind::Content
+        };
+
+        Ok(ConflictInfo {
+            path: path.clone(),
+            kind,
+            conflict_state: ConflictState::Unresolved,
+            base_revision,
+            theirs_revision,
+            yours_revision: base_revision,
+            published_by: theirs.map_or_else(String::new, |file_revision| file_revision.author.clone()),
+            published_time_unix_ms_utc,
+            theirs_metadata,
+        })
+    }
+
+    /// Returns a copy of the directory at the given path within the tree for the given revision
+    fn directory_at(
+        &self,
+        path: &RelativePath,
+        revision: Option<&RevisionSelector>,
+    ) -> Result<Directory, WorkspaceApiError> {
+        let tree = self.tree(revision)?;
+        find_directory(&tree, path).cloned()
+    }
+
+    /// Returns every file with pending changes at or below the given workspace scope path, at its depot path but
+    /// ordered by workspace path
+    fn pending_changes(&self, scope: &RelativePath) -> Vec<PendingChange> {
+        let mut changes = self
+            .full_directory_tree
+            .files()
+            .filter(|(path, _)| self.in_scope(path, scope))
+            .filter_map(|(path, entry)| match entry.info() {
+                DirectoryEntryType::File {
+                    metadata, change_state, ..
+                } if *change_state != ChangeState::Unchanged => Some(PendingChange {
+                    path,
+                    change_state: *change_state,
+                    metadata: metadata.clone(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by_cached_key(|change| self.workspace_path(&change.path));
+
+        changes
+    }
+
+    /// Returns every conflict at or below the given scope path, ordered by path
+    fn list_conflicts(&self, scope: &RelativePath) -> Result<Vec<ConflictInfo>, WorkspaceApiError> {
+        self.check_scope(scope)?;
+
+        let mut conflicts = self
+            .conflicts
+            .values()
+            .filter_map(|info| {
+                let path = self.workspace_path(&info.path).filter(|path| path.starts_with(scope))?;
+                Some(ConflictInfo { path, ..info.clone() })
+            })
+            .collect::<Vec<_>>();
+        conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(conflicts)
+    }
+
+    /// Resolves the conflict for the file at the given depot path, returning the updated entry and the resulting watch
+    /// events
+    fn resolve_conflict(
+        &mut self,
+        path: &RelativePath,
+        resolution: Resolution,
+    ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        self.file_change_state(path)?;
+        let Some(info) = self
+            .conflicts
+            .get(path)
+            .filter(|info| info.conflict_state == ConflictState::Unresolved)
+        else {
+            return Err(WorkspaceApiError::NotConflicted(path.clone()));
+        };
+
+        let theirs_metadata = info.theirs_metadata.clone();
+        let mut merged_content = None;
+        let events = self.update_file(path, |metadata, change_state, conflict_state| {
+            match resolution {
+                Resolution::AcceptTheirs => match theirs_metadata {
+                    Some(theirs_metadata) => *metadata = theirs_metadata,
+                    None => *change_state = ChangeState::Deleted,
+                },
+                Resolution::AcceptYours => {}
+                Resolution::Merged(content) => {
+                    *metadata = FileMetadata::new(content.len() as u64, now_unix_ms());
+                    merged_content = Some((metadata.clone(), content));
+                }
+            }
+            *conflict_state = ConflictState::Resolved;
+        })?;
+        if let Some((metadata, content)) = merged_content {
+            self.file_contents.insert((path.clone(), metadata), content);
+        }
+        if let Some(info) = self.conflicts.get_mut(path) {
+            info.conflict_state = ConflictState::Resolved;
+        }
+
+        Ok((find_entry(&self.full_directory_tree, path)?.clone(), events))
+    }
+
+    /// Returns the submitted revisions of the file at the given workspace path, newest first.  Revisions from before
+    /// the file was moved into the view are left out.
+    fn file_history(
+        &self,
+        path: &RelativePath,
+        options: &HistoryOptions,
+    ) -> Result<Vec<FileRevision>, WorkspaceApiError> {
+        let exists = match find_entry(&self.workspace_tree(), path) {
+            Ok(entry) if matches!(entry.info(), DirectoryEntryType::Directory(_)) => {
+                return Err(WorkspaceApiError::IsADirectory(path.clone()));
+            }
+            Ok(_) => true,
+            Err(WorkspaceApiError::NotFound(_)) => false,
+            Err(error) => return Err(error),
+        };
+
+        let mut revisions = vec![];
+        let mut current_path = self.depot_path(path)?;
+        let mut before_revision = None;
+        loop {
+            let mut path_revisions = self
+                .history
+                .iter()
+                .filter(|file_revision| {
+                    file_revision.path == current_path
+                        && before_revision.is_none_or(|before_revision| file_revision.revision < before_revision)
+                })
+                .collect::<Vec<_>>();
+            path_revisions.sort_by_key(|file_revision| Reverse(file_revision.revision));
+
+            // Revisions before a move belong to whichever file previously had the path, so stop at the move
+            let mut moved_from = None;
+            for file_revision in path_revisions {
+                revisions.extend(self.workspace_revision(file_revision));
+                if let FileAction::Move { from } = &file_revision.action {
+                    moved_from = Some((from.clone(), file_revision.revision));
+                    break;
+                }
+            }
+
+            match moved_from {
+                Some((from, revision)) if options.follow_renames => {
+                    current_path = from;
+                    before_revision = Some(revision);
+                }
+                _ => break,
+            }
+        }
+
+        if revisions.is_empty() && !exists {
+            return Err(WorkspaceApiError::NotFound(path.clone()));
+        }
+        if let Some(limit) = options.limit {
+            revisions.truncate(limit.get() as usize);
+        }
+
+        Ok(revisions)
+    }
+
+    /// Returns the changeset submitted as the given revision, built from the file history.  Only the changes to files
+    /// in the view are included.
+    fn changeset(&self, id: Revision) -> Option<Changeset> {
+        let mut file_revisions = self
+            .history
+            .iter()
+            .filter(|file_revision| file_revision.revision == id)
+            .peekable();
+        let first = *file_revisions.peek()?;
+
+        let mut changes = file_revisions
+            .filter_map(|file_revision| {
+                Some(ChangesetChange {
+                    path: self.workspace_path(&file_revision.path)?,
+                    change_state: match file_revision.action {
+                        FileAction::Add | FileAction::Move { .. } => ChangeState::Added,
+                        FileAction::Edit => ChangeState::Modified,
+                        FileAction::Delete => ChangeState::Deleted,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Some(Changeset {
+            id,
+            author: first.author.clone(),
+            submitted_time_unix_ms_utc: first.submitted_time_unix_ms_utc,
+            description: first.description.clone(),
+            changes,
+        })
+    }
+
+    /// Returns the changesets with revisions in the given range affecting the path filter, newest first
+    fn list_changesets(&self, range: impl RangeBounds<Revision>, path_filter: Option<&RelativePath>) -> Vec<Changeset> {
+        let revisions = self
+            .history
+            .iter()
+            .filter(|file_revision| {
+                range.contains(&file_revision.revision)
+                    && path_filter.is_none_or(|path_filter| self.in_scope(&file_revision.path, path_filter))
+            })
+            .map(|file_revision| file_revision.revision)
+            .collect::<BTreeSet<_>>();
+
+        revisions
+            .into_iter()
+            .rev()
+            .filter_map(|revision| self.changeset(revision))
+            .collect()
+    }
+
+    /// Reverts the pending changes at or below the given paths, returning the reverted files and the resulting watch
+    /// events
+    fn revert(
+        &mut self,
+        paths: &[RelativePath],
+        options: &RevertOptions,
+    ) -> Result<(Vec<RevertedFile>, Vec<WatchEvent>), WorkspaceApiError> {
+        let mut changes = vec![];
+        for path in paths {
+            self.check_scope(path)?;
+            changes.extend(self.pending_changes(path));
+        }
+        changes.sort_by_cached_key(|change| self.workspace_path(&change.path));
+        changes.dedup_by(|a, b| a.path == b.path);
+        if options.unchanged_only {
+            changes.retain(|change| {
+                change.change_state == ChangeState::Modified
+                    && self.base_metadata.get(&change.path) == Some(&change.metadata)
+            });
+        }
+
+        let mut reverted = vec![];
+        let mut events = vec![];
+        for change in changes {
+            let path = self
+                .workspace_path(&change.path)
+                .expect("Pending changes should be in the view");
+            self.changelists.remove(&change.path);
+            self.moved_from.remove(&change.path);
+            let entry = if change.change_state == ChangeState::Added {
+                events.push(self.remove_entry(&change.path)?);
+                if options.keep_local_content {
+                    self.local_files.insert(path.clone(), change.metadata);
+                }
+                None
+            } else {
+                let restored_metadata = match self.base_metadata.get(&change.path) {
+                    Some(base_metadata) if !options.keep_local_content => base_metadata.clone(),
+                    _ => change.metadata,
+                };
+                events.extend(
+                    self.update_file(&change.path, |metadata, change_state, conflict_state| {
+                        *metadata = restored_metadata;
+                        *change_state = ChangeState::Unchanged;
+                        // Any in-progress resolve is discarded, but incoming changes remain
+                        if *conflict_state != ConflictState::Incoming {
+                            *conflict_state = ConflictState::None;
+                        }
+                    })?,
+                );
+                let entry = find_entry(&self.full_directory_tree, &change.path)?.clone();
+                Some(self.workspace_entry(&change.path, entry))
+            };
+            reverted.push(RevertedFile {
+                path,
+                reverted_change_state: change.change_state,
+                entry,
+            });
+        }
+
+        Ok((reverted, events))
+    }
+
+    /// Submits the targeted pending changes, returning the new revision and the resulting watch events
+    fn submit(
+        &mut self,
+        target: SubmitTarget,
+        description: &str,
+    ) -> Result<(Revision, Vec<WatchEvent>), WorkspaceApiError> {
+        let changes = match target {
+            SubmitTarget::Paths(paths) => {
+                let mut changes = vec![];
+                for path in paths {
+                    self.check_scope(&path)?;
+                    let path_changes = self.pending_changes(&path);
+                    if path_changes.is_empty() {
+                        return Err(WorkspaceApiError::NotOpened(path));
+                    }
+                    changes.extend(path_changes);
+                }
+                changes.sort_by_cached_key(|change| self.workspace_path(&change.path));
+                changes.dedup_by(|a, b| a.path == b.path);
+                changes
+            }
+            SubmitTarget::Changelist(name) => self
+                .pending_changes(&RelativePath::default())
+                .into_iter()
+                .filter(|change| self.changelist_of(&change.path) == name)
+                .collect(),
+        };
+        if changes.is_empty() {
+            return Err(WorkspaceApiError::NothingToSubmit);
+        }
+
+        self.on_depot(|state| state.submit_changes(changes, description))
+    }
+
+    /// Submits the given pending changes to files at their depot paths, returning the new revision and the resulting
+    /// watch events
+    fn submit_changes(
+        &mut self,
+        changes: Vec<PendingChange>,
+        description: &str,
+    ) -> Result<(Revision, Vec<WatchEvent>), WorkspaceApiError> {
+        let mut out_of_date = vec![];
+        let mut unresolved = vec![];
+        for change in &changes {
+            self.check_not_locked_by_other(&change.path)?;
+            if let DirectoryEntryType::File { conflict_state, .. } =
+                find_entry(&self.full_directory_tree, &change.path)?.info()
+            {
+                match conflict_state {
+                    ConflictState::Incoming => out_of_date.push(change.path.clone()),
+                    ConflictState::Unresolved => unresolved.push(change.path.clone()),
+                    ConflictState::None | ConflictState::Resolved => {}
+                }
+            }
+        }
+        if !out_of_date.is_empty() {
+            return Err(WorkspaceApiError::OutOfDate(out_of_date));
+        }
+        if !unresolved.is_empty() {
+            return Err(WorkspaceApiError::UnresolvedConflicts(unresolved));
+        }
+
+        let head_revision = self.head_revision();
+        let revision = Revision::new(head_revision.number() + 1);
+        let submitted_time_unix_ms_utc = now_unix_ms();
+        // Snapshot the new revision, so the submitted files are not seen as incoming changes which would undo them
+        if !self.snapshots.is_empty() {
+            self.snapshots.push(MockSnapshot {
+                name: format!("submit-{}", revision),
+                revision,
+                submitted_time_unix_ms_utc,
+                tree: self.submitted_tree(head_revision, &changes)?,
+            });
+        }
+        let mut events = vec![];
+        for change in changes {
+            let action = match change.change_state {
+                ChangeState::Added => match self.moved_from.remove(&change.path) {
+                    Some(from) => FileAction::Move { from },
+                    None => FileAction::Add,
+                },
+                ChangeState::Modified => FileAction::Edit,
+                ChangeState::Deleted => FileAction::Delete,
+                ChangeState::Unchanged => unreachable!("Pending changes should never be unchanged"),
+            };
+            self.history.push(FileRevision {
+                revision,
+                path: change.path.clone(),
+                author: self.user_name.clone(),
+                submitted_time_unix_ms_utc,
+                description: description.to_string(),
+                action,
+                metadata: (change.change_state != ChangeState::Deleted).then(|| change.metadata.clone()),
+            });
+
+            if change.change_state == ChangeState::Deleted {
+                events.push(self.remove_entry(&change.path)?);
+                self.base_metadata.remove(&change.path);
+            } else {
+                events.extend(self.update_file(&change.path, |_, change_state, conflict_state| {
+                    *change_state = ChangeState::Unchanged;
+                    *conflict_state = ConflictState::None;
+                })?);
+                // Submitting releases the lock, so the next user can check the file out
+                events.extend(self.set_lock(&change.path, None)?);
+                self.base_metadata.insert(change.path.clone(), change.metadata);
+            }
+            self.changelists.remove(&change.path);
+        }
+
+        self.advance_head_revision(revision);
+        // Only a workspace which was at the head revision has every file of the new revision
+        if self.have_revision == head_revision {
+            self.have_revision = revision;
+        }
+        Ok((revision, events))
+    }
+
+    /// Returns the depot tree at the given revision with the given pending changes applied
+    fn submitted_tree(&self, revision: Revision, changes: &[PendingChange]) -> Result<Directory, WorkspaceApiError> {
+        let mut files = self
+            .revision_tree(&RevisionSelector::Revision(revision))?
+            .files()
+            .map(|(path, entry)| (path, entry.clone()))
+            .collect::<BTreeMap<_, _>>();
+        for change in changes {
+            if change.change_state == ChangeState::Deleted {
+                files.remove(&change.path);
+                continue;
+            }
+            let name = change.path.file_name().expect("File path should have a file name");
+            let entry = DirectoryEntry::new(
+                name.to_string(),
+                DirectoryEntryType::File {
+                    metadata: change.metadata.clone(),
+                    change_state: ChangeState::Unchanged,
+                    conflict_state: ConflictState::None,
+                    lock: Lock::Unlocked,
+                },
+            );
+            files.insert(change.path.clone(), entry);
+        }
+
+        Ok(files_tree(files.into_iter().collect()))
+    }
+
+    /// Opens a new local file at the given workspace path for add at the given depot path, returning the added entry
+    /// and the resulting watch events
+    fn mark_for_add(
+        &mut self,
+        path: &RelativePath,
+        local_path: &RelativePath,
+    ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        self.check_vacant(path)?;
+        let metadata = self
+            .local_files
+            .get(local_path)
+            .cloned()
+            .ok_or_else(|| WorkspaceApiError::NotFound(path.clone()))?;
+
+        let parent_path = path.parent().expect("Vacant path should not be the root path");
+        let mut events = self.create_directories(&parent_path)?;
+        let event = self.insert_entry(
+            path,
+            DirectoryEntryType::File {
+                metadata,
+                change_state: ChangeState::Added,
+                conflict_state: ConflictState::None,
+                lock: Lock::Unlocked,
+            },
+        )?;
+        let entry = event.entry.clone();
+        events.push(event);
+        self.local_files.remove(local_path);
+
+        Ok((entry, events))
+    }
+
+    /// Opens the file at the given depot path for edit, taking its metadata from the local file at the given
+    /// workspace path if one is set, returning the updated entry and the resulting watch events
+    fn mark_for_edit(
+        &mut self,
+        path: &RelativePath,
+        local_path: &RelativePath,
+    ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        if self.file_change_state(path)? == ChangeState::Deleted {
+            return Err(WorkspaceApiError::AlreadyOpened(path.clone(), ChangeState::Deleted));
+        }
+        self.check_not_locked_by_other(path)?;
+
+        let local_metadata = self.local_files.remove(local_path);
+        let events = self.update_file(path, |metadata, change_state, _| {
+            if let Some(local_metadata) = local_metadata {
+                *metadata = local_metadata;
+            }
+            if *change_state == ChangeState::Unchanged {
+                *change_state = ChangeState::Modified;
+            }
+        })?;
+
+        Ok((find_entry(&self.full_directory_tree, path)?.clone(), events))
+    }
+
+    /// Opens the file at the given depot path for delete, discarding the local file at the given workspace path,
+    /// returning the updated entry and the resulting watch events
+    fn mark_for_delete(
+        &mut self,
+        path: &RelativePath,
+        local_path: &RelativePath,
+    ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        if self.file_change_state(path)? == ChangeState::Added {
+            return Err(WorkspaceApiError::AlreadyOpened(path.clone(), ChangeState::Added));
+        }
+        self.check_not_locked_by_other(path)?;
+
+        self.local_files.remove(local_path);
+        let events = self.update_file(path, |_, change_state, _| *change_state = ChangeState::Deleted)?;
+
+        Ok((find_entry(&self.full_directory_tree, path)?.clone(), events))
+    }
+
+    /// Moves the file between the given depot paths, returning the entry at the new path and the resulting watch events
+    fn mark_for_move(
+        &mut self,
+        from: &RelativePath,
+        to: &RelativePath,
+    ) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        let source = find_entry(&self.full_directory_tree, from)?.clone();
+        let DirectoryEntryType::File {
+            metadata,
+            change_state,
+            conflict_state,
+            ..
+        } = source.info().clone()
+        else {
+            return Err(WorkspaceApiError::IsADirectory(from.clone()));
+        };
+        if change_state == ChangeState::Deleted {
+            return Err(WorkspaceApiError::AlreadyOpened(from.clone(), ChangeState::Deleted));
+        }
+        self.check_not_locked_by_other(from)?;
+        self.check_vacant(to)?;
+
+        let parent_path = to.parent().expect("Vacant path should not be the root path");
+        let mut events = self.create_directories(&parent_path)?;
+        let changelist = self.changelists.remove(from);
+        let original_path = self
+            .moved_from
+            .remove(from)
+            .or_else(|| (change_state != ChangeState::Added).then(|| from.clone()));
+        if change_state == ChangeState::Added {
+            events.push(self.remove_entry(from)?);
+        } else {
+            events.extend(self.update_file(from, |_, change_state, _| *change_state = ChangeState::Deleted)?);
+            if let Some(changelist) = &changelist {
+                self.changelists.insert(from.clone(), changelist.clone());
+            }
+        }
+
+        let event = self.insert_entry(
+            to,
+            DirectoryEntryType::File {
+                metadata,
+                change_state: ChangeState::Added,
+                conflict_state,
+                lock: Lock::Unlocked,
+            },
+        )?;
+        let entry = event.entry.clone();
+        events.push(event);
+        if let Some(changelist) = changelist {
+            self.changelists.insert(to.clone(), changelist);
+        }
+        if let Some(original_path) = original_path {
+            self.moved_from.insert(to.clone(), original_path);
+        }
+
+        Ok((entry, events))
+    }
+
+    /// Creates a new workspace with the given definition
+    fn create_workspace(&mut self, spec: WorkspaceSpec) -> Result<WorkspaceSpec, WorkspaceApiError> {
+        if self.workspaces.contains_key(&spec.name) {
+            return Err(WorkspaceApiError::WorkspaceAlreadyExists(spec.name));
+        }
+        self.workspaces.insert(spec.name.clone(), spec.clone());
+
+        Ok(spec)
+    }
+
+    /// Replaces the definition of an existing workspace
+    fn update_workspace(&mut self, spec: WorkspaceSpec) -> Result<WorkspaceSpec, WorkspaceApiError> {
+        let Some(existing) = self.workspaces.get_mut(&spec.name) else {
+            return Err(WorkspaceApiError::WorkspaceNotFound(spec.name));
+        };
+        *existing = spec.clone();
+
+        Ok(spec)
+    }
+
+    /// Deletes a workspace other than the current workspace
+    fn delete_workspace(&mut self, name: &str) -> Result<(), WorkspaceApiError> {
+        if name == self.current_workspace {
+            return Err(WorkspaceApiError::WorkspaceInUse(name.to_string()));
+        }
+        self.workspaces
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| WorkspaceApiError::WorkspaceNotFound(name.to_string()))
+    }
+
+    /// Locks the file at the given depot path for the current user, returning the updated entry and the resulting
+    /// watch events
+    fn lock(&mut self, path: &RelativePath) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        self.file_change_state(path)?;
+        self.check_not_locked_by_other(path)?;
+        let events = self.set_lock(path, Some(self.user_name.clone()))?;
+
+        Ok((find_entry(&self.full_directory_tree, path)?.clone(), events))
+    }
+
+    /// Unlocks the file at the given depot path locked by the current user, returning the updated entry and the
+    /// resulting watch events
+    fn unlock(&mut self, path: &RelativePath) -> Result<(DirectoryEntry, Vec<WatchEvent>), WorkspaceApiError> {
+        self.file_change_state(path)?;
+        self.check_not_locked_by_other(path)?;
+        if !matches!(
+            find_entry(&self.full_directory_tree, path)?.info(),
+            DirectoryEntryType::File {
+                lock: Lock::LockedByYou,
+                ..
+            }
+        ) {
+            return Err(WorkspaceApiError::NotLocked(path.clone()));
+        }
+        let events = self.set_lock(path, None)?;
+
+        Ok((find_entry(&self.full_directory_tree, path)?.clone(), events))
+    }
+
+    /// Returns every lock on a file at or below the given scope path, ordered by path
+    fn list_locks(&self, scope: &RelativePath) -> Result<Vec<FileLock>, WorkspaceApiError> {
+        self.check_scope(scope)?;
+
+        let mut locks = self
+            .workspace_tree()
+            .files()
+            .filter(|(path, _)| path.starts_with(scope))
+            .filter_map(|(path, entry)| match entry.info() {
+                DirectoryEntryType::File { lock, .. } if *lock != Lock::Unlocked => Some(FileLock {
+                    path,
+                    lock: lock.clone(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        locks.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(locks)
+    }
+
+    /// Checks that the file at the given path is not locked by another user
+    fn check_not_locked_by_other(&self, path: &RelativePath) -> Result<(), WorkspaceApiError> {
+        match find_entry(&self.full_directory_tree, path)?.info() {
+            DirectoryEntryType::File {
+                lock: Lock::LockedByOther { owner },
+                ..
+            } => Err(WorkspaceApiError::LockedByOther(path.clone(), owner.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the owner of the lock on a file, or unlocks it if `None`, returning the resulting watch events
+    fn set_lock(&mut self, path: &RelativePath, owner: Option<String>) -> Result<Vec<WatchEvent>, WorkspaceApiError> {
+        let new_lock = match owner {
+            None => Lock::Unlocked,
+            Some(owner) if owner == self.user_name => Lock::LockedByYou,
+            Some(owner) => Lock::LockedByOther { owner },
+        };
+        self.update_file_info(path, |info| {
+            if let DirectoryEntryType::File { lock, .. } = info {
+                *lock = new_lock;
+            }
+        })
+    }
+
+    /// Returns the change state of the file at the given path
+    fn file_change_state(&self, path: &RelativePath) -> Result<ChangeState, WorkspaceApiError> {
+        match find_entry(&self.full_directory_tree, path)?.info() {
+            DirectoryEntryType::File { change_state, .. } => Ok(*change_state),
+            DirectoryEntryType::Directory(_) => Err(WorkspaceApiError::IsADirectory(path.clone())),
+        }
+    }
+
+    /// Checks that nothing exists at the given path, so a new entry can be created there
+    fn check_vacant(&self, path: &RelativePath) -> Result<(), WorkspaceApiError> {
+        if path.is_empty() {
+            return Err(WorkspaceApiError::AlreadyExists(path.clone()));
+        }
+
+        match find_entry(&self.full_directory_tree, path) {
+            Ok(_) => Err(WorkspaceApiError::AlreadyExists(path.clone())),
+            Err(WorkspaceApiError::NotFound(_)) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Creates the directory at the given path along with any missing ancestors, returning the matching watch events
+    fn create_directories(&mut self, path: &RelativePath) -> Result<Vec<WatchEvent>, WorkspaceApiError> {
+        let mut missing = vec![];
+        let mut current = Some(path.clone());
+        while let Some(directory_path) = current.filter(|path| !path.is_empty()) {
+            match find_directory(&self.full_directory_tree, &directory_path) {
+                Ok(_) => break,
+                Err(WorkspaceApiError::NotFound(_)) => {
+                    current = directory_path.parent();
+                    missing.push(directory_path);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        missing
+            .into_iter()
+            .rev()
+            .map(|directory_path| {
+                let directory = Directory::new(directory_path.clone(), vec![]);
+                self.insert_entry(&directory_path, DirectoryEntryType::Directory(Some(directory)))
+            })
+            .collect()
+    }
+
+    /// Returns the name of the changelist the file at the given path belongs to
+    fn changelist_of(&self, path: &RelativePath) -> &str {
+        self.changelists.get(path).map_or(DEFAULT_CHANGELIST, String::as_str)
+    }
+
+    /// Inserts a new entry at the given path, returning the matching watch event
+    fn insert_entry(&mut self, path: &RelativePath, info: DirectoryEntryType) -> Result<WatchEvent, WorkspaceApiError> {
+        let (Some(parent_path), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(WorkspaceApiError::AlreadyExists(path.clone()));
+        };
+        // Check the parent up front, so the error describes the path that was missing
+        if find_directory(&self.full_directory_tree, &parent_path)?
+            .entry(name)
+            .is_some()
+        {
+            return Err(WorkspaceApiError::AlreadyExists(path.clone()));
+        }
+
+        let entry = DirectoryEntry::new(name.to_string(), info);
+        let event = WatchEvent {
+            kind: WatchEventKind::EntryAdded,
+            path: path.clone(),
+            entry: entry.to_unloaded(),
+        };
+
+        self.full_directory_tree
+            .update_directory(&parent_path, |parent| parent.insert_entry(entry))
+            .expect("Parent directory should exist");
+
+        Ok(event)
+    }
+
+    /// Removes the entry at the given path, returning the matching watch event
+    fn remove_entry(&mut self, path: &RelativePath) -> Result<WatchEvent, WorkspaceApiError> {
+        find_entry(&self.full_directory_tree, path)?;
+
+        let parent_path = path.parent().expect("Entry path should not be the root path");
+        let name = path.file_name().expect("Entry path should have a file name");
+        let entry = self
+            .full_directory_tree
+            .update_directory(&parent_path, |parent| parent.remove_entry(name))
+            .flatten()
+            .expect("Entry should exist");
+        self.conflicts
+            .retain(|conflict_path, _| !conflict_path.starts_with(path));
+        self.moved_from.retain(|moved_path, _| !moved_path.starts_with(path));
+
+        Ok(WatchEvent {
+            kind: WatchEventKind::EntryRemoved,
+            path: path.clone(),
+            entry: entry.to_unloaded(),
+        })
+    }
+
+    /// Updates the file at the given path, returning a watch event for each part of the file that changed
+    fn update_file(
+        &mut self,
+        path: &RelativePath,
+        f: impl FnOnce(&mut FileMetadata, &mut ChangeState, &mut ConflictState),
+    ) -> Result<Vec<WatchEvent>, WorkspaceApiError> {
+        self.update_file_info(path, |info| {
+            if let DirectoryEntryType::File {
+                metadata,
+                change_state,
+                conflict_state,
+                ..
+            } = info
+            {
+                f(metadata, change_state, conflict_state);
+            }
+        })
+    }
+
+    /// Updates the type information of the file at the given path, which must remain a file, returning a watch event
+    /// for each part of the file that changed
+    fn update_file_info(
+        &mut self,
+        path: &RelativePath,
+        f: impl FnOnce(&mut DirectoryEntryType),
+    ) -> Result<Vec<WatchEvent>, WorkspaceApiError> {
+        let before = find_entry(&self.full_directory_tree, path)?.clone();
+        let DirectoryEntryType::File {
+            metadata: before_metadata,
+            change_state: before_change_state,
+            conflict_state: before_conflict_state,
+            lock: before_lock,
+        } = before.info()
+        else {
+            return Err(WorkspaceApiError::IsADirectory(path.clone()));
+        };
+
+        let parent_path = path.parent().expect("Entry path should not be the root path");
+        let name = path.file_name().expect("Entry path should have a file name");
+        let after = self
+            .full_directory_tree
+            .update_directory(&parent_path, |parent| {
+                let entry = parent.entry_mut(name).expect("Entry should exist");
+                f(entry.info_mut());
+                entry.clone()
+            })
+            .expect("Parent directory should exist");
+
+        let DirectoryEntryType::File {
+            metadata,
+            change_state,
+            conflict_state,
+            lock,
+        } = after.info()
+        else {
+            unreachable!("Entry should still be a file");
+        };
+        if matches!(conflict_state, ConflictState::None | ConflictState::Incoming) {
+            self.conflicts.remove(path);
+        }
+
+        let changes = [
+            (change_state != before_change_state, WatchEventKind::ChangeStateChanged),
+            (
+                conflict_state != before_conflict_state,
+                WatchEventKind::ConflictStateChanged,
+            ),
+            (metadata != before_metadata, WatchEventKind::MetadataChanged),
+            (lock != before_lock, WatchEventKind::LockStateChanged),
+        ];
+        Ok(changes
+            .into_iter()
+            .filter(|(changed, _)| *changed)
+            .map(|(_, kind)| WatchEvent {
+                kind,
+                path: path.clone(),
+                entry: after.clone(),
+            })
+            .collect())
+    }
+}
+
+impl WorkspaceApi for MockWorkspaceApi {
+    async fn fetch_directory(
+        &self,
+        path: &RelativePath,
+        options: DirectoryFetchOptions,
+    ) -> Result<Directory, WorkspaceApiError> {
+        self.delay().await;
+
+        let mut directory = self.state().directory_at(path, options.revision.as_ref())?;
+        apply_fetch_options(&mut directory, options);
+
+        Ok(directory)
+    }
+
+    async fn fetch_directories(
+        &self,
+        paths: &[RelativePath],
+        options: DirectoryFetchOptions,
+    ) -> Result<BTreeMap<RelativePath, Result<Directory, WorkspaceApiError>>, WorkspaceApiError> {
+        // A single delay for the whole batch
+        self.delay().await;
+
+        // Sorting by path means ancestors are always fetched before their descendants
+        let mut paths = paths.to_vec();
+        paths.sort();
+        paths.dedup();
+
+        let mut results = BTreeMap::new();
+        for path in paths {
+            let already_loaded = results.values().any(|result: &Result<Directory, WorkspaceApiError>| {
+                result
+                    .as_ref()
+                    .is_ok_and(|directory| directory.find_directory(&path).is_some())
+            });
+            if already_loaded {
+                continue;
+            }
+
+            let result = self
+                .state()
+                .directory_at(&path, options.revision.as_ref())
+                .map(|mut directory| {
+                    apply_fetch_options(&mut directory, options.clone());
+                    directory
+                });
+            results.insert(path, result);
+        }
+
+        Ok(results)
+    }
+
+    fn fetch_directory_stream<'a>(
+        &'a self,
+        path: &RelativePath,
+        options: DirectoryFetchOptions,
+    ) -> impl futures::Stream<Item = Result<Directory, WorkspaceApiError>> + use<'a> {
+        let path = path.clone();
+        let depth_limit = options.depth_limit;
+
+        let root = async move {
+            self.delay().await;
+
+            // Filter the full tree up front, the depth limit is applied as the tree is walked
+            let mut directory = self.state().directory_at(&path, options.revision.as_ref())?;
+            apply_fetch_options(
+                &mut directory,
+                DirectoryFetchOptions {
+                    depth_limit: None,
+                    ..options
+                },
+            );
+            Ok(directory)
+        };
+
+        stream::once(root).flat_map(move |result| match result {
+            Ok(directory) => {
+                let queue = VecDeque::from([(directory, 0)]);
+                stream::unfold(queue, move |mut queue| async move {
+                    let (mut directory, depth) = queue.pop_front()?;
+                    self.chunk_delay().await;
+
+                    let sub_directories = directory.unload_subdirectories();
+                    if depth_limit.is_none_or(|depth_limit| depth < depth_limit) {
+                        queue.extend(sub_directories.into_iter().map(|sub_dir| (sub_dir, depth + 1)));
+                    }
+
+                    Some((Ok(directory), queue))
+                })
+                .left_stream()
+            }
+            Err(error) => stream::once(future::ready(Err(error))).right_stream(),
+        })
+    }
+
+    async fn fetch_directory_page(
+        &self,
+        path: &RelativePath,
+        options: DirectoryFetchOptions,
+    ) -> Result<DirectoryPage, WorkspaceApiError> {
+        self.delay().await;
+
+        let mut directory = self.state().directory_at(path, options.revision.as_ref())?;
+        let start_after = options.cursor.clone();
+        let page_size = options.page_size;
+        apply_fetch_options(&mut directory, options);
+
+        // The mock cursor is simply the name of the last entry in the previous page
+        let next_cursor = directory
+            .retain_page(start_after.as_ref().map(PageCursor::as_str), page_size)
+            .map(PageCursor::new);
+
+        Ok(DirectoryPage { directory, next_cursor })
+    }
+
+    async fn fetch_entry(
+        &self,
+        path: &RelativePath,
+        options: EntryFetchOptions,
+    ) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        let state = self.state();
+        let tree = state.tree(options.revision.as_ref())?;
+        let entry = if path.is_empty() {
+            match options.directory_options {
+                Some(_) => DirectoryEntry::new(String::new(), DirectoryEntryType::Directory(Some(tree.into_owned()))),
+                None => return Ok(DirectoryEntry::new(String::new(), DirectoryEntryType::Directory(None))),
+            }
+        } else {
+            find_entry(&tree, path)?.clone()
+        };
+
+        Ok(match (entry.info(), options.directory_options) {
+            (DirectoryEntryType::Directory(Some(directory)), Some(directory_options)) => {
+                let mut directory = directory.clone();
+                apply_fetch_options(&mut directory, directory_options);
+                DirectoryEntry::new(entry.name().to_string(), DirectoryEntryType::Directory(Some(directory)))
+            }
+            _ => entry.to_unloaded(),
+        })
+    }
+
+    async fn list_pending_changes(&self, scope: &RelativePath) -> Result<Vec<Changelist>, WorkspaceApiError> {
+        self.delay().await;
+
+        let state = self.state();
+        state.check_scope(scope)?;
+        let mut changelists = BTreeMap::<&str, Vec<PendingChange>>::new();
+        for change in state.pending_changes(scope) {
+            changelists
+                .entry(state.changelist_of(&change.path))
+                .or_default()
+                .push(state.workspace_change(change));
+        }
+
+        let default_changes = changelists.remove(DEFAULT_CHANGELIST).unwrap_or_default();
+        let default_changelist = Changelist {
+            name: DEFAULT_CHANGELIST.to_string(),
+            changes: default_changes,
+        };
+        let named_changelists = changelists.into_iter().map(|(name, changes)| Changelist {
+            name: name.to_string(),
+            changes,
+        });
+
+        Ok(std::iter::once(default_changelist).chain(named_changelists).collect())
+    }
+
+    async fn move_to_changelist(&self, paths: &[RelativePath], changelist: &str) -> Result<(), WorkspaceApiError> {
+        self.delay().await;
+
+        let mut state = self.state();
+        let tree = state.workspace_tree();
+        for path in paths {
+            match find_entry(&tree, path)?.info() {
+                DirectoryEntryType::File { change_state, .. } if *change_state != ChangeState::Unchanged => {}
+                DirectoryEntryType::File { .. } => return Err(WorkspaceApiError::NotOpened(path.clone())),
+                DirectoryEntryType::Directory(_) => return Err(WorkspaceApiError::IsADirectory(path.clone())),
+            }
+        }
+        drop(tree);
+
+        for path in paths {
+            let depot_path = state.depot_path(path)?;
+            if changelist == DEFAULT_CHANGELIST {
+                state.changelists.remove(&depot_path);
+            } else {
+                state.changelists.insert(depot_path, changelist.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn revert(
+        &self,
+        paths: &[RelativePath],
+        options: RevertOptions,
+    ) -> Result<Vec<RevertedFile>, WorkspaceApiError> {
+        self.delay().await;
+
+        let (reverted, events) = self.state().revert(paths, &options)?;
+        self.push_watch_events(events);
+
+        Ok(reverted)
+    }
+
+    async fn list_conflicts(&self, scope: &RelativePath) -> Result<Vec<ConflictInfo>, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().list_conflicts(scope)
+    }
+
+    async fn resolve_conflict(
+        &self,
+        path: &RelativePath,
+        resolution: Resolution,
+    ) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, |state, path| state.resolve_conflict(path, resolution))
+    }
+
+    async fn lock(&self, path: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, MockState::lock)
+    }
+
+    async fn unlock(&self, path: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, MockState::unlock)
+    }
+
+    async fn list_locks(&self, scope: &RelativePath) -> Result<Vec<FileLock>, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().list_locks(scope)
+    }
+
+    async fn file_history(
+        &self,
+        path: &RelativePath,
+        options: HistoryOptions,
+    ) -> Result<Vec<FileRevision>, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().file_history(path, &options)
+    }
+
+    async fn fetch_changeset(&self, id: Revision) -> Result<Changeset, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state()
+            .changeset(id)
+            .ok_or(WorkspaceApiError::RevisionNotFound(id))
+    }
+
+    async fn list_changesets(
+        &self,
+        range: impl RangeBounds<Revision>,
+        path_filter: Option<&RelativePath>,
+    ) -> Result<Vec<Changeset>, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self.state().list_changesets(range, path_filter))
+    }
+
+    async fn diff_trees(
+        &self,
+        from: TreeVersion,
+        to: TreeVersion,
+        scope: &RelativePath,
+    ) -> Result<Directory, WorkspaceApiError> {
+        self.delay().await;
+
+        let state = self.state();
+        let from_tree = state.version_tree(&from)?;
+        let to_tree = state.version_tree(&to)?;
+        let find_scope = |tree| match find_directory(tree, scope) {
+            Ok(directory) => Ok(Some(directory)),
+            Err(WorkspaceApiError::NotFound(_)) => Ok(None),
+            Err(error) => Err(error),
+        };
+
+        let empty = Directory::new(scope.clone(), vec![]);
+        match (find_scope(&from_tree)?, find_scope(&to_tree)?) {
+            (None, None) => Err(WorkspaceApiError::NotFound(scope.clone())),
+            (from_directory, to_directory) => Ok(model::diff_directories(
+                from_directory.unwrap_or(&empty),
+                to_directory.unwrap_or(&empty),
+            )),
+        }
+    }
+
+    async fn diff_file(
+        &self,
+        path: &RelativePath,
+        from: TreeVersion,
+        to: TreeVersion,
+    ) -> Result<FileDiff, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().diff_file(path, &from, &to)
+    }
+
+    async fn preview_sync(
+        &self,
+        scope: &RelativePath,
+        target: Option<RevisionSelector>,
+    ) -> Result<Vec<IncomingChange>, WorkspaceApiError> {
+        self.delay().await;
+
+        let (_, changes) = self.state().incoming_changes(scope, target.as_ref())?;
+        Ok(changes)
+    }
+
+    fn sync<'a>(
+        &'a self,
+        scope: &RelativePath,
+        target: Option<RevisionSelector>,
+    ) -> impl futures::Stream<Item = Result<SyncProgress, WorkspaceApiError>> + use<'a> {
+        let scope = scope.clone();
+        // Only a sync of the whole workspace brings every file to the target revision
+        let whole_workspace = scope.is_empty();
+
+        let plan = async move {
+            self.delay().await;
+            let mut state = self.state();
+            let (revision, changes) = state.incoming_changes(&scope, target.as_ref())?;
+            if changes.is_empty() && whole_workspace {
+                state.have_revision = revision;
+            }
+            Ok((revision, changes))
+        };
+
+        stream::once(plan).flat_map(move |result| match result {
+            Ok((revision, changes)) => {
+                let files_total = changes.len() as u64;
+                let bytes_total = changes.iter().map(IncomingChange::size_bytes).sum();
+                // Each change is made as the stream is polled, so dropping the stream stops the sync between files
+                stream::unfold(
+                    (changes.into_iter(), 0, 0),
+                    move |(mut changes, files_done, bytes_done)| async move {
+                        let change = changes.next()?;
+                        self.chunk_delay().await;
+
+                        let result = {
+                            let mut state = self.state();
+                            let result = state
+                                .depot_path(&change.path)
+                                .and_then(|path| state.on_depot(|state| state.sync_change(&path, &change, revision)));
+                            if result.is_ok() && changes.as_slice().is_empty() && whole_workspace {
+                                state.have_revision = revision;
+                            }
+                            result
+                        };
+                        match result {
+                            Ok(events) => {
+                                self.push_watch_events(events);
+                                let progress = SyncProgress {
+                                    files_done: files_done + 1,
+                                    files_total,
+                                    bytes_done: bytes_done + change.size_bytes(),
+                                    bytes_total,
+                                    change,
+                                };
+                                let next = (changes, progress.files_done, progress.bytes_done);
+                                Some((Ok(progress), next))
+                            }
+                            // Errors end the stream
+                            Err(error) => Some((Err(error), (Vec::new().into_iter(), files_done, bytes_done))),
+                        }
+                    },
+                )
+                .left_stream()
+            }
+            Err(error) => stream::once(future::ready(Err(error))).right_stream(),
+        })
+    }
+
+    async fn shelve(&self, changelist: &str, description: &str) -> Result<Shelf, WorkspaceApiError> {
+        self.delay().await;
+
+        let (owner, contents) = {
+            let state = self.state();
+            (state.user_name.clone(), state.shelf_contents(changelist)?)
+        };
+        let mut shelves = self.shelf_store.shelves();
+        shelves.last_id += 1;
+        let shelf = Shelf {
+            id: ShelfId::new(shelves.last_id),
+            owner,
+            description: description.to_string(),
+            created_time_unix_ms_utc: now_unix_ms(),
+            changelist: changelist.to_string(),
+            contents,
+        };
+        shelves.shelves.insert(shelf.id, shelf.clone());
+
+        Ok(shelf)
+    }
+
+    async fn list_shelves(&self, user_filter: Option<&str>) -> Result<Vec<Shelf>, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self
+            .shelf_store
+            .shelves()
+            .shelves
+            .values()
+            .filter(|shelf| user_filter.is_none_or(|user| shelf.owner == user))
+            .map(|shelf| {
+                let mut shelf = shelf.clone();
+                shelf.contents.prune_to_depth(0);
+                shelf
+            })
+            .collect())
+    }
+
+    async fn fetch_shelf(&self, id: ShelfId) -> Result<Shelf, WorkspaceApiError> {
+        self.delay().await;
+
+        let shelf = self
+            .shelf_store
+            .shelves()
+            .shelves
+            .get(&id)
+            .cloned()
+            .ok_or(WorkspaceApiError::ShelfNotFound(id))?;
+        let contents = self.state().apply_view(Cow::Owned(shelf.contents)).into_owned();
+
+        Ok(Shelf { contents, ..shelf })
+    }
+
+    async fn unshelve(&self, id: ShelfId, changelist: &str) -> Result<Vec<PendingChange>, WorkspaceApiError> {
+        self.delay().await;
+
+        let contents = self
+            .shelf_store
+            .shelves()
+            .shelves
+            .get(&id)
+            .map(|shelf| shelf.contents.clone())
+            .ok_or(WorkspaceApiError::ShelfNotFound(id))?;
+        let (changes, events) = {
+            let mut state = self.state();
+            let (changes, events) = state.on_depot(|state| state.unshelve(&contents, changelist))?;
+            let mut changes = changes
+                .into_iter()
+                .map(|change| state.workspace_change(change))
+                .collect::<Vec<_>>();
+            changes.sort_by(|a, b| a.path.cmp(&b.path));
+            (changes, events)
+        };
+        self.push_watch_events(events);
+
+        Ok(changes)
+    }
+
+    async fn create_label(
+        &self,
+        name: &str,
+        source: LabelSource,
+        scope: &RelativePath,
+        description: &str,
+    ) -> Result<Label, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().create_label(name, &source, scope, description)
+    }
+
+    async fn list_labels(&self) -> Result<Vec<Label>, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self.state().labels.values().map(|(label, _)| label.clone()).collect())
+    }
+
+    async fn delete_label(&self, name: &str) -> Result<(), WorkspaceApiError> {
+        self.delay().await;
+
+        self.state()
+            .labels
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| WorkspaceApiError::LabelNotFound(name.to_string()))
+    }
+
+    async fn list_workspaces(&self) -> Result<Vec<WorkspaceSpec>, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self.state().workspaces.values().cloned().collect())
+    }
+
+    async fn current_workspace(&self) -> Result<WorkspaceSpec, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self.state().workspace_spec().clone())
+    }
+
+    async fn create_workspace(&self, spec: WorkspaceSpec) -> Result<WorkspaceSpec, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().create_workspace(spec)
+    }
+
+    async fn update_workspace(&self, spec: WorkspaceSpec) -> Result<WorkspaceSpec, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().update_workspace(spec)
+    }
+
+    async fn delete_workspace(&self, name: &str) -> Result<(), WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().delete_workspace(name)
+    }
+
+    async fn list_streams(&self) -> Result<Vec<Stream>, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self
+            .state()
+            .streams
+            .values()
+            .map(|mock_stream| mock_stream.stream.clone())
+            .collect())
+    }
+
+    async fn current_stream(&self) -> Result<Stream, WorkspaceApiError> {
+        self.delay().await;
+
+        Ok(self.state().current_stream().clone())
+    }
+
+    async fn create_stream(
+        &self,
+        name: &str,
+        parent: &str,
+        stream_type: StreamType,
+    ) -> Result<Stream, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().create_stream(name, parent, stream_type)
+    }
+
+    async fn switch_stream(&self, name: &str) -> Result<Stream, WorkspaceApiError> {
+        self.delay().await;
+
+        self.state().switch_stream(name)
+    }
+
+    async fn submit(&self, target: SubmitTarget, description: &str) -> Result<Revision, WorkspaceApiError> {
+        self.delay().await;
+
+        let (revision, events) = self.state().submit(target, description)?;
+        self.push_watch_events(events);
+
+        Ok(revision)
+    }
+
+    async fn mark_for_add(&self, path: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, |state, depot_path| state.mark_for_add(depot_path, path))
+    }
+
+    async fn mark_for_edit(&self, path: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, |state, depot_path| state.mark_for_edit(depot_path, path))
+    }
+
+    async fn mark_for_delete(&self, path: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        self.on_file(path, |state, depot_path| state.mark_for_delete(depot_path, path))
+    }
+
+    async fn mark_for_move(&self, from: &RelativePath, to: &RelativePath) -> Result<DirectoryEntry, WorkspaceApiError> {
+        self.delay().await;
+
+        let (entry, events) = {
+            let mut state = self.state();
+            let from = state.depot_path(from)?;
+            let to = state.depot_path(to)?;
+            let (entry, events) = state.on_depot(|state| state.mark_for_move(&from, &to))?;
+            (state.workspace_entry(&to, entry), events)
+        };
+        self.push_watch_events(events);
+
+        Ok(entry)
+    }
+
+    fn watch<'a>(
+        &'a self,
+        path: &RelativePath,
+        recursive: bool,
+    ) -> impl futures::Stream<Item = Result<WatchEvent, WorkspaceApiError>> + use<'a> {
+        let path = path.clone();
+        // Subscribe immediately, so events sent before the stream is first polled are not missed
+        let receiver = self.watch_events.subscribe();
+
+        stream::unfold(receiver, |mut receiver| async move {
+            let result = match receiver.recv().await {
+                Ok(event) => Ok(event),
+                Err(RecvError::Lagged(count)) => Err(WorkspaceApiError::Protocol(format!(
+                    "Watcher fell behind, {} events were missed",
+                    count
+                ))),
+                Err(RecvError::Closed) => return None,
+            };
+            Some((result, receiver))
+        })
+        .filter(move |result| {
+            let matches = match result {
+                Ok(event) => {
+                    event.path == path
+                        || if recursive {
+                            event.path.starts_with(&path)
+                        } else {
+                            event.path.parent().as_ref() == Some(&path)
+                        }
+                }
+                Err(_) => true,
+            };
+            future::ready(matches)
+        })
+    }
+}
+
+/// Finds the directory at the given path within the mock directory tree
+fn find_directory<'a>(tree: &'a Directory, path: &RelativePath) -> Result<&'a Directory, WorkspaceApiError> {
+    if path.is_empty() {
+        return Ok(tree);
+    }
+
+    match find_entry(tree, path)?.info() {
+        DirectoryEntryType::Directory(Some(directory)) => Ok(directory),
+        DirectoryEntryType::Directory(None) => {
+            // The mock directory tree should not contain any unloaded directories
+            Err(WorkspaceApiError::Protocol(format!(
+                "Mock directory tree contains an unloaded directory at '{}'",
+                path
+            )))
+        }
+        DirectoryEntryType::File { .. } => Err(WorkspaceApiError::NotADirectory(path.clone())),
+    }
+}
+
+/// Finds the entry at the given non-empty path within the mock directory tree
+fn find_entry<'a>(tree: &'a Directory, path: &RelativePath) -> Result<&'a DirectoryEntry, WorkspaceApiError> {
+    let (Some(parent_path), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(WorkspaceApiError::NotFound(path.clone()));
+    };
+
+    let parent = find_directory(tree, &parent_path).map_err(|error| match error {
+        // A file in the middle of the path means the path cannot exist
+        WorkspaceApiError::NotADirectory(_) => WorkspaceApiError::NotFound(path.clone()),
+        error => error,
+    })?;
+
+    // Inefficient but acceptable for a mock
+    parent
+        .entries()
+        .iter()
+        .find(|entry| entry.name() == name)
+        .ok_or_else(|| WorkspaceApiError::NotFound(path.clone()))
+}
+
+/// Returns the current time in Unix milliseconds UTC
+fn now_unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Returns the metadata of every file in the directory tree which is in the depot, i.e. not opened for add
+fn base_metadata(tree: &Directory) -> HashMap<RelativePath, FileMetadata> {
+    tree.files()
+        .filter_map(|(path, entry)| match entry.info() {
+            DirectoryEntryType::File {
+                metadata, change_state, ..
+            } if *change_state != ChangeState::Added => Some((path, metadata.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns a copy of the directory tree containing only the files whose change state matches the predicate, along with
+/// their ancestor directories
+fn retain_files(tree: &Directory, predicate: impl Fn(ChangeState) -> bool) -> Directory {
+    let mut tree = tree.clone();
+    tree.retain_recursive(&mut |entry| match entry.info() {
+        DirectoryEntryType::File { change_state, .. } => predicate(*change_state),
+        DirectoryEntryType::Directory(_) => false,
+    });
+    tree
+}
+
+/// Returns a tree containing only the given files, each at its path, along with their ancestor directories
+fn files_tree(files: Vec<(RelativePath, DirectoryEntry)>) -> Directory {
+    let mut tree = Directory::new(RelativePath::default(), vec![]);
+    for (path, entry) in files {
+        let mut ancestors = vec![];
+        let mut current = path.parent();
+        while let Some(ancestor) = current.filter(|ancestor| !ancestor.is_empty()) {
+            current = ancestor.parent();
+            ancestors.push(ancestor);
+        }
+
+        for ancestor in ancestors.into_iter().rev() {
+            let parent_path = ancestor.parent().expect("Ancestor should not be the root path");
+            let name = ancestor.file_name().expect("Ancestor should have a file name");
+            tree.update_directory(&parent_path, |parent| {
+                if parent.entry(name).is_none() {
+                    let directory = Directory::new(ancestor.clone(), vec![]);
+                    parent.insert_entry(DirectoryEntry::new(
+                        name.to_string(),
+                        DirectoryEntryType::Directory(Some(directory)),
+                    ));
+                }
+            });
+        }
+
+        let parent_path = path.parent().expect("File path should not be the root path");
+        tree.update_directory(&parent_path, |parent| parent.insert_entry(entry));
+    }
+
+    tree
+}
+
+/// Returns a copy of the directory tree as it is in the depot, with every file unchanged, conflict free and unlocked
+fn depot_tree(directory: &Directory) -> Directory {
+    let entries = directory
+        .entries()
+        .iter()
+        .map(|entry| {
+            let info = match entry.info() {
+                DirectoryEntryType::File { metadata, .. } => DirectoryEntryType::File {
+                    metadata: metadata.clone(),
+                    change_state: ChangeState::Unchanged,
+                    conflict_state: ConflictState::None,
+                    lock: Lock::Unlocked,
+                },
+                DirectoryEntryType::Directory(Some(sub_directory)) => {
+                    DirectoryEntryType::Directory(Some(depot_tree(sub_directory)))
+                }
+                DirectoryEntryType::Directory(None) => DirectoryEntryType::Directory(None),
+            };
+            DirectoryEntry::new(entry.name().to_string(), info)
+        })
+        .collect();
+
+    Directory::new(directory.relative_path().clone(), entries)
+}
+
+/// Applies the filters and depth limit from the fetch options to a fully loaded directory
+fn apply_fetch_options(directory: &mut Directory, options: DirectoryFetchOptions) {
+    if options.change_state_filter.is_some() || options.conflict_state_filter.is_some() {
+        directory.retain_states(options.change_state_filter, options.conflict_state_filter);
+    }
+
+    if let Some(filter_string) = options.filter_string.filter(|filter| !filter.is_empty()) {
+        // Filter the full tree before pruning, so matches below the depth limit keep their ancestors visible
+        let filter_string = filter_string.to_lowercase();
+        directory.retain_recursive(&mut |entry| entry.name().to_lowercase().contains(&filter_string));
+    }
+
+    if let Some(depth_limit) = options.depth_limit {
+        // Cull entries beyond the depth limit
+        directory.prune_to_depth(depth_limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v1::model::*;
+    use std::num::NonZeroU32;
+
+    #[tokio::test]
+    async fn test_fetch_directory() {
+        let mut root = Directory::new(path(""), vec![]);
+
+        let mut sub_dir = Directory::new(path("subdir"), vec![]);
+
+        let mut sub_sub_dir = Directory::new(path("subdir/nested"), vec![]);
+
+        sub_sub_dir.push_entry(DirectoryEntry::new(
+            "file.txt".into(),
+            DirectoryEntryType::File {
+                metadata: FileMetadata::new(0, 0),
+                change_state: Default::default(),
+                conflict_state: Default::default(),
+                lock: Lock::Unlocked,
+            },
+        ));
+
+        sub_dir.push_entry(DirectoryEntry::new(
+            "nested".into(),
+            DirectoryEntryType::Directory(Some(sub_sub_dir)),
+        ));
+
+        root.push_entry(DirectoryEntry::new(
+            "subdir".into(),
+            DirectoryEntryType::Directory(Some(sub_dir)),
+        ));
+
+        //println!("Constructed mock directory tree: {}", serde_json::to_string_pretty(&root).unwrap());
+
+        let mock_api = MockWorkspaceApi::with_directory_tree(root);
+
+        let fetch_options = DirectoryFetchOptions::default();
+
+        let result = mock_api
+            .fetch_directory(&path("missing/path"), fetch_options.clone())
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+
+        let dir = mock_api
+            .fetch_directory(&path("subdir"), fetch_options.clone())
+            .await
+            .expect("subdir should exist");
+        assert_eq!(dir.relative_path().to_string(), "subdir");
+
+        let dir = mock_api
+            .fetch_directory(&path("subdir/nested"), fetch_options.clone())
+            .await
+            .expect("subdir/nested should exist");
+        assert_eq!(dir.relative_path().to_string(), "subdir/nested");
+
+        let result = mock_api
+            .fetch_directory(&path("subdir/nested/file.txt"), fetch_options.clone())
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotADirectory(_))));
+
+        let result = mock_api
+            .fetch_directory(&path("subdir/nested/file.txt/child"), fetch_options.clone())
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_json_data() {
+        let test_json_data = include_str!("test_data/lyra.json");
+        let mut mock_api = MockWorkspaceApi::default();
+
+        let result = mock_api
+            .fetch_directory(&path(""), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert!(result.entries().is_empty(), "Initial mock directory should be empty");
+
+        mock_api
+            .set_directory_tree_from_json_str(test_json_data)
+            .await
+            .expect("Setting directory tree from JSON should succeed");
+
+        let result = mock_api
+            .fetch_directory(&path(""), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+
+        assert!(
+            !result.entries().is_empty(),
+            "Mock directory should not be empty after setting JSON data"
+        );
+
+        // The fixture predates per-state counts, so they should be recomputed when loading it
+        let file_count = count_files(&result);
+        assert!(file_count > 0);
+        assert_eq!(result.change_state_counts().get(ChangeState::Unchanged), file_count);
+        assert_eq!(result.change_state_counts().total(), file_count);
+        assert_eq!(result.conflict_state_counts().get(ConflictState::None), file_count);
+        assert_eq!(result.lock_state_counts().get(LockState::Unlocked), file_count);
+
+        // No pruning means first entry should be "Build"
+        let first_entry = &result.entries()[0];
+        assert_eq!(first_entry.name(), "Build", "First entry should be 'Build'");
+
+        // Directory should be loaded
+        assert!(
+            matches!(first_entry.info(), DirectoryEntryType::Directory(Some(_))),
+            "First entry should be a loaded directory"
+        );
+
+        // Test depth limiting
+        let result = mock_api
+            .fetch_directory(
+                &path(""),
+                DirectoryFetchOptions {
+                    depth_limit: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // Pruning means first entry should still be "Build", but unloaded
+        let first_entry = &result.entries()[0];
+        assert_eq!(first_entry.name(), "Build", "First entry should be 'Build'");
+        assert!(
+            matches!(first_entry.info(), DirectoryEntryType::Directory(None)),
+            "First entry should be an unloaded directory due to depth limit"
+        );
+        assert_eq!(result.change_state_counts().total(), file_count);
+
+        // Counts should survive a serde round-trip, even though the pruned directories can no longer be counted
+        let json = serde_json::to_string(&result).unwrap();
+        let round_tripped: Directory = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped.change_state_counts(), result.change_state_counts());
+        assert_eq!(round_tripped.conflict_state_counts(), result.conflict_state_counts());
+        assert_eq!(round_tripped.change_states(), result.change_states());
+        assert_eq!(round_tripped.lock_state_counts(), result.lock_state_counts());
+
+        // Data serialized before locks were added has its counts recomputed from its entries
+        let result = mock_api
+            .fetch_directory(&path(""), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        let mut json_value = serde_json::to_value(&result).unwrap();
+        let json_object = json_value.as_object_mut().unwrap();
+        json_object.remove("lock_states");
+        json_object.remove("lock_state_counts");
+        let without_locks: Directory = serde_json::from_value(json_value).unwrap();
+        assert_eq!(without_locks.change_state_counts(), result.change_state_counts());
+        assert_eq!(without_locks.lock_state_counts(), result.lock_state_counts());
+        assert_eq!(without_locks.lock_states(), LockState::Unlocked);
+    }
+
+    #[tokio::test]
+    async fn test_filter_string() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry("docs", vec![new_file("readme.md")]),
+                new_directory_entry(
+                    "src",
+                    vec![
+                        new_file("lib.rs"),
+                        new_file("main.rs"),
+                        new_directory_entry("util", vec![new_file("Main_util.rs"), new_file("strings.rs")]),
+                    ],
+                ),
+            ],
+        ));
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                filter_string: Some("MAIN".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["src", "src/main.rs", "src/util", "src/util/Main_util.rs"],
+            "Only matches and their ancestors should be kept"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                filter_string: Some("doc".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["docs", "docs/readme.md"],
+            "Matching directories should keep their contents"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                depth_limit: Some(0),
+                filter_string: Some("main".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["src (unloaded)"],
+            "Ancestors of matches below the depth limit should be kept, but unloaded"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "src",
+            DirectoryFetchOptions {
+                filter_string: Some("strings".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(names, vec!["src/util", "src/util/strings.rs"]);
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                filter_string: Some("missing".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(names.is_empty(), "No entries should match");
+    }
+
+    #[tokio::test]
+    async fn test_state_filters() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_file_with_states("added.uasset", ChangeState::Added, ConflictState::None),
+                        new_file_with_states("conflicted.uasset", ChangeState::Modified, ConflictState::Unresolved),
+                        new_file("unchanged.uasset"),
+                    ],
+                ),
+                new_directory_entry(
+                    "source",
+                    vec![
+                        new_file_with_states("modified.cpp", ChangeState::Modified, ConflictState::None),
+                        new_directory_entry("private", vec![new_file("unchanged.cpp")]),
+                    ],
+                ),
+                new_file("unchanged.txt"),
+            ],
+        ));
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                change_state_filter: Some(ChangeState::Modified.into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["content", "content/conflicted.uasset", "source", "source/modified.cpp"],
+            "Only modified files and their ancestors should be kept"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                conflict_state_filter: Some(ConflictState::Unresolved | ConflictState::Incoming),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(names, vec!["content", "content/conflicted.uasset"]);
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                change_state_filter: Some(ChangeState::Modified | ChangeState::Added),
+                conflict_state_filter: Some(ConflictState::None.into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["content", "content/added.uasset", "source", "source/modified.cpp"],
+            "Files should match both filters to be kept"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                change_state_filter: Some(ChangeState::Modified.into()),
+                filter_string: Some("cpp".into()),
+                depth_limit: Some(0),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            names,
+            vec!["source (unloaded)"],
+            "State filters should combine with the name filter and depth limit"
+        );
+
+        let names = fetch_names(
+            &mock_api,
+            "",
+            DirectoryFetchOptions {
+                change_state_filter: Some(ChangeState::Deleted.into()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(names.is_empty(), "No files should match");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_entry() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry(
+                "content",
+                vec![
+                    new_file_with_states("conflicted.uasset", ChangeState::Modified, ConflictState::Unresolved),
+                    new_directory_entry("maps", vec![new_file("level.umap")]),
+                ],
+            )],
+        ));
+
+        let entry = mock_api
+            .fetch_entry(&path("content/conflicted.uasset"), EntryFetchOptions::default())
+            .await
+            .expect("File entry should exist");
+        assert_eq!(entry.name(), "conflicted.uasset");
+        assert!(matches!(
+            entry.info(),
+            DirectoryEntryType::File {
+                change_state: ChangeState::Modified,
+                conflict_state: ConflictState::Unresolved,
+                ..
+            }
+        ));
+
+        let entry = mock_api
+            .fetch_entry(&path("content"), EntryFetchOptions::default())
+            .await
+            .expect("Directory entry should exist");
+        assert_eq!(entry.name(), "content");
+        assert!(
+            matches!(entry.info(), DirectoryEntryType::Directory(None)),
+            "Directories should be unloaded by default"
+        );
+
+        let entry = mock_api
+            .fetch_entry(
+                &path("content"),
+                EntryFetchOptions {
+                    directory_options: Some(DirectoryFetchOptions {
+                        depth_limit: Some(0),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Directory entry should exist");
+        let DirectoryEntryType::Directory(Some(directory)) = entry.info() else {
+            panic!("Directory should be loaded when directory options are given");
+        };
+        assert_eq!(directory.relative_path().as_str(), "content");
+        assert_eq!(directory.entries().len(), 2);
+        assert!(matches!(
+            directory.entries()[1].info(),
+            DirectoryEntryType::Directory(None)
+        ));
+
+        let entry = mock_api
+            .fetch_entry(&RelativePath::default(), EntryFetchOptions::default())
+            .await
+            .expect("Root entry should exist");
+        assert_eq!(entry.name(), "");
+        assert!(matches!(entry.info(), DirectoryEntryType::Directory(None)));
+
+        for missing_path in ["missing", "content/missing.uasset", "content/conflicted.uasset/child"] {
+            let result = mock_api
+                .fetch_entry(&path(missing_path), EntryFetchOptions::default())
+                .await;
+            assert!(
+                matches!(result, Err(WorkspaceApiError::NotFound(_))),
+                "'{}' should not exist",
+                missing_path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_directories() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry("a", vec![new_directory_entry("b", vec![new_file("c.txt")])]),
+                new_directory_entry("d", vec![new_file("e.txt")]),
+                new_file("f.txt"),
+            ],
+        ));
+
+        let paths = ["a/b", "a", "d", "missing", "f.txt", "a/b"].map(path);
+
+        let results = mock_api
+            .fetch_directories(&paths, DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_api.request_count(),
+            1,
+            "Latency should be charged once for the whole batch"
+        );
+
+        let keys = results.keys().map(RelativePath::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["a", "d", "f.txt", "missing"],
+            "Paths loaded within another result should not be returned separately"
+        );
+
+        let a = results[&paths[1]].as_ref().unwrap();
+        let b = a.find_directory(&paths[0]).expect("a/b should be loaded within a");
+        assert_eq!(b.relative_path(), &paths[0]);
+        assert_eq!(results[&paths[2]].as_ref().unwrap().entries().len(), 1);
+        assert!(matches!(results[&paths[3]], Err(WorkspaceApiError::NotFound(_))));
+        assert!(matches!(results[&paths[4]], Err(WorkspaceApiError::NotADirectory(_))));
+
+        // When the depth limit leaves a descendant unloaded, it should be returned separately
+        let results = mock_api
+            .fetch_directories(
+                &paths[..3],
+                DirectoryFetchOptions {
+                    depth_limit: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let keys = results.keys().map(RelativePath::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "a/b", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_directory_stream() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "a",
+                    vec![
+                        new_directory_entry("a1", vec![new_directory_entry("a1x", vec![new_file("deep.txt")])]),
+                        new_file("a.txt"),
+                    ],
+                ),
+                new_directory_entry("b", vec![new_directory_entry("b1", vec![new_file("b1.txt")])]),
+                new_file("root.txt"),
+            ],
+        ));
+        mock_api.set_chunk_latency_range_ms(1..3);
+
+        let directories = mock_api
+            .fetch_directory_stream(&RelativePath::default(), DirectoryFetchOptions::default())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Stream should not error");
+
+        let paths = directories
+            .iter()
+            .map(|directory| directory.relative_path().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["", "a", "b", "a/a1", "b/b1", "a/a1/a1x"],
+            "Directories should be streamed level by level"
+        );
+
+        for directory in &directories {
+            assert!(
+                directory
+                    .entries()
+                    .iter()
+                    .all(|entry| !matches!(entry.info(), DirectoryEntryType::Directory(Some(_)))),
+                "Streamed directories should not contain loaded sub-directories"
+            );
+        }
+        assert_eq!(
+            directories[0].change_state_counts().total(),
+            4,
+            "Streamed directories should keep their aggregates"
+        );
+
+        // Reassembling the streamed directories should give the same tree as a regular fetch
+        let mut directories = directories.into_iter();
+        let mut assembled = directories.next().unwrap();
+        for directory in directories {
+            assembled
+                .insert_loaded_directory(directory)
+                .expect("Streamed directories should be inserted into their parent");
+        }
+        let mut assembled_names = vec![];
+        collect_names(&assembled, &mut assembled_names);
+        let fetched_names = fetch_names(&mock_api, "", DirectoryFetchOptions::default()).await;
+        assert_eq!(assembled_names, fetched_names);
+
+        // The depth limit should stop the stream at that level
+        let paths = mock_api
+            .fetch_directory_stream(
+                &path("a"),
+                DirectoryFetchOptions {
+                    depth_limit: Some(1),
+                    ..Default::default()
+                },
+            )
+            .map(|result| result.unwrap().relative_path().to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(paths, vec!["a", "a/a1"]);
+
+        // Dropping the stream part way through should cancel the remaining fetches
+        let first = mock_api
+            .fetch_directory_stream(&RelativePath::default(), DirectoryFetchOptions::default())
+            .take(1)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(first.len(), 1);
+
+        let results = mock_api
+            .fetch_directory_stream(&path("missing"), DirectoryFetchOptions::default())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(
+            matches!(results.as_slice(), [Err(WorkspaceApiError::NotFound(_))]),
+            "Errors should end the stream"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_directory_page() {
+        let file_names = ["b.uasset", "a.uasset", "e.uasset", "c.uasset", "d.uasset"];
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry(
+                "assets",
+                file_names.iter().map(|name| new_file(name)).collect(),
+            )],
+        ));
+
+        let path = path("assets");
+        let mut options = DirectoryFetchOptions {
+            page_size: NonZeroU32::new(2),
+            ..Default::default()
+        };
+
+        let mut pages = vec![];
+        loop {
+            let page = mock_api.fetch_directory_page(&path, options.clone()).await.unwrap();
+            assert_eq!(
+                page.directory.change_state_counts().total(),
+                5,
+                "Aggregates should describe the full directory"
+            );
+
+            let names = page
+                .directory
+                .entries()
+                .iter()
+                .map(|entry| entry.name().to_string())
+                .collect::<Vec<_>>();
+            pages.push(names);
+
+            match page.next_cursor {
+                Some(cursor) => options.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(
+            pages,
+            vec![
+                vec!["a.uasset", "b.uasset"],
+                vec!["c.uasset", "d.uasset"],
+                vec!["e.uasset"]
+            ],
+            "Pages should be ordered by path"
+        );
+
+        // Continuing from a cursor should be stable when entries are added before it
+        let first_page = mock_api
+            .fetch_directory_page(
+                &path,
+                DirectoryFetchOptions {
+                    page_size: NonZeroU32::new(2),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let mut file_names = file_names.to_vec();
+        file_names.push("aa.uasset");
+        mock_api.set_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry(
+                "assets",
+                file_names.iter().map(|name| new_file(name)).collect(),
+            )],
+        ));
+
+        let second_page = mock_api
+            .fetch_directory_page(
+                &path,
+                DirectoryFetchOptions {
+                    page_size: NonZeroU32::new(2),
+                    cursor: first_page.next_cursor,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(second_page.directory.entries()[0].name(), "c.uasset");
+
+        // Without a page size every remaining entry is returned
+        let page = mock_api
+            .fetch_directory_page(&path, DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(page.directory.entries().len(), 6);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_file("a.uasset"),
+                        new_directory_entry("maps", vec![new_file("level.umap")]),
+                    ],
+                ),
+                new_file("other.txt"),
+            ],
+        ));
+
+        let content_path = path("content");
+        let recursive_watch = mock_api.watch(&content_path, true);
+        let immediate_watch = mock_api.watch(&content_path, false);
+        let root_watch = mock_api.watch(&RelativePath::default(), false);
+
+        let a_path = path("content/a.uasset");
+        let level_path = path("content/maps/level.umap");
+        let new_path = path("content/new.uasset");
+        let mutations = [
+            MockMutation::SetChangeState {
+                path: a_path.clone(),
+                change_state: ChangeState::Modified,
+            },
+            MockMutation::SetMetadata {
+                path: level_path.clone(),
+                metadata: FileMetadata::new(100, 1000),
+            },
+            MockMutation::AddFile {
+                path: new_path.clone(),
+                metadata: FileMetadata::new(10, 1000),
+                change_state: ChangeState::Added,
+                conflict_state: ConflictState::None,
+            },
+            MockMutation::RemoveEntry {
+                path: path("other.txt"),
+            },
+            MockMutation::SetConflictState {
+                path: a_path.clone(),
+                conflict_state: ConflictState::Incoming,
+            },
+        ];
+        for mutation in mutations {
+            mock_api.apply_mutation(mutation).expect("Mutation should apply");
+        }
+
+        // Setting a state to its current value should not emit an event
+        mock_api
+            .apply_mutation(MockMutation::SetChangeState {
+                path: a_path.clone(),
+                change_state: ChangeState::Modified,
+            })
+            .unwrap();
+
+        mock_api.push_watch_event(WatchEvent {
+            kind: WatchEventKind::EntryRemoved,
+            path: new_path.clone(),
+            entry: new_file("new.uasset"),
+        });
+
+        let events = recursive_watch.take(5).collect::<Vec<_>>().await;
+        let events = events
+            .into_iter()
+            .map(|event| {
+                let event = event.expect("Watch should not error");
+                (event.kind, event.path.to_string())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (WatchEventKind::ChangeStateChanged, "content/a.uasset".to_string()),
+                (WatchEventKind::MetadataChanged, "content/maps/level.umap".to_string()),
+                (WatchEventKind::EntryAdded, "content/new.uasset".to_string()),
+                (WatchEventKind::ConflictStateChanged, "content/a.uasset".to_string()),
+                (WatchEventKind::EntryRemoved, "content/new.uasset".to_string()),
+            ]
+        );
+
+        let events = immediate_watch.take(4).collect::<Vec<_>>().await;
+        let paths = events
+            .iter()
+            .map(|event| event.as_ref().unwrap().path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "content/a.uasset",
+                "content/new.uasset",
+                "content/a.uasset",
+                "content/new.uasset"
+            ],
+            "Non-recursive watches should not include nested entries"
+        );
+
+        let event = root_watch.take(1).collect::<Vec<_>>().await.pop().unwrap().unwrap();
+        assert_eq!(event.kind, WatchEventKind::EntryRemoved);
+        assert_eq!(event.path.as_str(), "other.txt");
+        assert_eq!(event.entry.name(), "other.txt");
+
+        // Aggregates should be kept up to date by the mutations
+        let content = mock_api
+            .fetch_directory(&content_path, DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            content.change_states(),
+            ChangeState::Added | ChangeState::Modified | ChangeState::Unchanged
+        );
+        assert_eq!(content.change_state_counts().get(ChangeState::Modified), 1);
+        assert_eq!(content.conflict_state_counts().get(ConflictState::Incoming), 1);
+        assert_eq!(content.change_state_counts().total(), 3);
+
+        // Invalid mutations should be rejected
+        let result = mock_api.apply_mutation(MockMutation::AddDirectory {
+            path: path("content/maps"),
+        });
+        assert!(matches!(result, Err(WorkspaceApiError::AlreadyExists(_))));
+        let result = mock_api.apply_mutation(MockMutation::SetChangeState {
+            path: path("content/maps"),
+            change_state: ChangeState::Modified,
+        });
+        assert!(matches!(result, Err(WorkspaceApiError::IsADirectory(_))));
+        let result = mock_api.apply_mutation(MockMutation::AddDirectory {
+            path: path("missing/dir"),
+        });
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_pending_changes() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_file_with_states("hero.uasset", ChangeState::Modified, ConflictState::None),
+                        new_file_with_states("added.uasset", ChangeState::Added, ConflictState::None),
+                        new_file("unchanged.uasset"),
+                    ],
+                ),
+                new_file_with_states("deleted.txt", ChangeState::Deleted, ConflictState::None),
+            ],
+        ));
+
+        let changelists = mock_api.list_pending_changes(&RelativePath::default()).await.unwrap();
+        assert_eq!(
+            changelist_paths(&changelists),
+            vec![(
+                DEFAULT_CHANGELIST,
+                vec!["content/added.uasset", "content/hero.uasset", "deleted.txt"]
+            )],
+            "All pending changes should start in the default changelist, ordered by path"
+        );
+        assert_eq!(changelists[0].changes[1].change_state, ChangeState::Modified);
+
+        let hero_path = path("content/hero.uasset");
+        mock_api
+            .move_to_changelist(std::slice::from_ref(&hero_path), "art")
+            .await
+            .unwrap();
+
+        let changelists = mock_api.list_pending_changes(&RelativePath::default()).await.unwrap();
+        assert_eq!(
+            changelist_paths(&changelists),
+            vec![
+                (DEFAULT_CHANGELIST, vec!["content/added.uasset", "deleted.txt"]),
+                ("art", vec!["content/hero.uasset"]),
+            ]
+        );
+
+        let changelists = mock_api.list_pending_changes(&path("content")).await.unwrap();
+        assert_eq!(
+            changelist_paths(&changelists),
+            vec![
+                (DEFAULT_CHANGELIST, vec!["content/added.uasset"]),
+                ("art", vec!["content/hero.uasset"]),
+            ],
+            "Only changes within the scope should be listed"
+        );
+
+        let changelists = mock_api.list_pending_changes(&path("deleted.txt")).await.unwrap();
+        assert_eq!(
+            changelist_paths(&changelists),
+            vec![(DEFAULT_CHANGELIST, vec!["deleted.txt"])]
+        );
+
+        let result = mock_api
+            .move_to_changelist(&[path("deleted.txt"), path("content/unchanged.uasset")], "art")
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotOpened(_))));
+        let result = mock_api.move_to_changelist(&[path("content")], "art").await;
+        assert!(matches!(result, Err(WorkspaceApiError::IsADirectory(_))));
+        let result = mock_api.list_pending_changes(&path("missing")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+
+        // Failed moves should not move any files, and files can be moved back to the default changelist
+        mock_api
+            .move_to_changelist(&[hero_path], DEFAULT_CHANGELIST)
+            .await
+            .unwrap();
+        let changelists = mock_api.list_pending_changes(&RelativePath::default()).await.unwrap();
+        assert_eq!(changelists.len(), 1);
+        assert_eq!(changelists[0].changes.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_submit() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "conflicts",
+                    vec![
+                        new_file_with_states("incoming.uasset", ChangeState::Modified, ConflictState::Incoming),
+                        new_file_with_states("unresolved.uasset", ChangeState::Modified, ConflictState::Unresolved),
+                    ],
+                ),
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_file_with_states("added.uasset", ChangeState::Added, ConflictState::None),
+                        new_file_with_states("deleted.uasset", ChangeState::Deleted, ConflictState::None),
+                        new_file_with_states("hero.uasset", ChangeState::Modified, ConflictState::Resolved),
+                    ],
+                ),
+                new_file("unchanged.txt"),
+            ],
+        ));
+        let watch = mock_api.watch(&path("content"), true);
+
+        let conflicts_path = path("conflicts");
+        let result = mock_api
+            .submit(SubmitTarget::Paths(vec![conflicts_path.clone()]), "Conflicted")
+            .await;
+        let Err(WorkspaceApiError::OutOfDate(paths)) = result else {
+            panic!("Submitting files with incoming changes should fail");
+        };
+        assert_eq!(paths, vec![path("conflicts/incoming.uasset")]);
+
+        mock_api
+            .apply_mutation(MockMutation::SetConflictState {
+                path: path("conflicts/incoming.uasset"),
+                conflict_state: ConflictState::None,
+            })
+            .unwrap();
+        let result = mock_api
+            .submit(SubmitTarget::Paths(vec![conflicts_path]), "Conflicted")
+            .await;
+        let Err(WorkspaceApiError::UnresolvedConflicts(paths)) = result else {
+            panic!("Submitting files with unresolved conflicts should fail");
+        };
+        assert_eq!(paths, vec![path("conflicts/unresolved.uasset")]);
+
+        let added_path = path("content/added.uasset");
+        mock_api
+            .move_to_changelist(std::slice::from_ref(&added_path), "art")
+            .await
+            .unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Changelist("art".into()), "Add asset")
+            .await
+            .unwrap();
+        assert_eq!(revision, Revision::new(1));
+
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![path("content")]), "Update content")
+            .await
+            .unwrap();
+        assert_eq!(revision, Revision::new(2), "Revisions should be sequential");
+
+        let content = mock_api
+            .fetch_directory(&path("content"), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        let names = content.entries().iter().map(|entry| entry.name()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["added.uasset", "hero.uasset"],
+            "Deleted files should be removed"
+        );
+        assert_eq!(content.change_states(), ChangeState::Unchanged);
+        assert_eq!(content.conflict_states(), ConflictState::None);
+
+        let root = mock_api
+            .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(root.change_state_counts().get(ChangeState::Modified), 2);
+        assert_eq!(root.change_state_counts().get(ChangeState::Unchanged), 3);
+
+        let events = watch
+            .take(4)
+            .map(|event| {
+                let event = event.unwrap();
+                (event.kind, event.path.to_string())
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                (WatchEventKind::ChangeStateChanged, "content/added.uasset".to_string()),
+                (WatchEventKind::EntryRemoved, "content/deleted.uasset".to_string()),
+                (WatchEventKind::ChangeStateChanged, "content/hero.uasset".to_string()),
+                (WatchEventKind::ConflictStateChanged, "content/hero.uasset".to_string()),
+            ]
+        );
+
+        let result = mock_api.submit(SubmitTarget::Changelist("art".into()), "Empty").await;
+        assert!(matches!(result, Err(WorkspaceApiError::NothingToSubmit)));
+        let result = mock_api
+            .submit(SubmitTarget::Paths(vec![path("unchanged.txt")]), "Unchanged")
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotOpened(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mark_for_changes() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry("content", vec![new_file("hero.uasset"), new_file("level.umap")]),
+                new_file("readme.txt"),
+            ],
+        ));
+        let watch = mock_api.watch(&path("build"), true);
+
+        // Adding requires a local file, and creates any missing parent directories
+        let result = mock_api.mark_for_add(&path("build/out/game.pak")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let pak_metadata = FileMetadata::new(4096, 1_700_000_000_000);
+        mock_api.set_local_file_metadata(path("build/out/game.pak"), pak_metadata.clone());
+        let entry = mock_api.mark_for_add(&path("build/out/game.pak")).await.unwrap();
+        assert_eq!(
+            file_info(&entry),
+            (pak_metadata.clone(), ChangeState::Added, ConflictState::None)
+        );
+        let events = watch
+            .take(3)
+            .map(|event| event.unwrap().path.to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events, vec!["build", "build/out", "build/out/game.pak"]);
+        let result = mock_api.mark_for_add(&path("content/hero.uasset")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::AlreadyExists(_))));
+
+        // Editing picks up the local metadata, and is idempotent
+        let hero_metadata = FileMetadata::new(2048, 1_700_000_000_000);
+        mock_api.set_local_file_metadata(path("content/hero.uasset"), hero_metadata.clone());
+        let entry = mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        assert_eq!(
+            file_info(&entry),
+            (hero_metadata.clone(), ChangeState::Modified, ConflictState::None)
+        );
+        let edited_again = mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        assert_eq!(file_info(&edited_again), file_info(&entry));
+        let result = mock_api.mark_for_edit(&path("content")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::IsADirectory(_))));
+
+        let entry = mock_api.mark_for_delete(&path("content/level.umap")).await.unwrap();
+        assert!(matches!(
+            entry.info(),
+            DirectoryEntryType::File {
+                change_state: ChangeState::Deleted,
+                ..
+            }
+        ));
+        let result = mock_api.mark_for_edit(&path("content/level.umap")).await;
+        assert!(matches!(
+            result,
+            Err(WorkspaceApiError::AlreadyOpened(_, ChangeState::Deleted))
+        ));
+        let result = mock_api.mark_for_delete(&path("build/out/game.pak")).await;
+        assert!(matches!(
+            result,
+            Err(WorkspaceApiError::AlreadyOpened(_, ChangeState::Added))
+        ));
+
+        // Moving a versioned file deletes the old path and adds the new one, keeping the changelist
+        mock_api
+            .move_to_changelist(&[path("content/hero.uasset")], "art")
+            .await
+            .unwrap();
+        let entry = mock_api
+            .mark_for_move(&path("content/hero.uasset"), &path("content/characters/hero.uasset"))
+            .await
+            .unwrap();
+        assert_eq!(
+            file_info(&entry),
+            (hero_metadata, ChangeState::Added, ConflictState::None)
+        );
+        let result = mock_api
+            .mark_for_move(&path("readme.txt"), &path("content/characters/hero.uasset"))
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::AlreadyExists(_))));
+
+        // Moving a file opened for add simply renames it
+        mock_api
+            .mark_for_move(&path("build/out/game.pak"), &path("build/game.pak"))
+            .await
+            .unwrap();
+
+        let changelists = mock_api.list_pending_changes(&RelativePath::default()).await.unwrap();
+        assert_eq!(
+            changelist_paths(&changelists),
+            vec![
+                (DEFAULT_CHANGELIST, vec!["build/game.pak", "content/level.umap"]),
+                ("art", vec!["content/characters/hero.uasset", "content/hero.uasset"]),
+            ]
+        );
+
+        let root = mock_api
+            .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        let content = root.find_directory(&path("content")).unwrap();
+        assert_eq!(content.change_state_counts().get(ChangeState::Added), 1);
+        assert_eq!(content.change_state_counts().get(ChangeState::Deleted), 2);
+        assert_eq!(
+            content.change_states(),
+            ChangeState::Added | ChangeState::Deleted,
+            "Aggregated states should be updated for every ancestor"
+        );
+        let build_out = root.find_directory(&path("build/out")).unwrap();
+        assert!(build_out.entries().is_empty());
+        assert_eq!(build_out.change_states(), ChangeStateSet::empty());
+    }
+
+    #[tokio::test]
+    async fn test_revert() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_sized_file("hero.uasset", 100, 1),
+                        new_sized_file("level.umap", 200, 1),
+                        new_sized_file("prop.uasset", 300, 1),
+                    ],
+                ),
+                new_sized_file("readme.txt", 400, 1),
+            ],
+        ));
+
+        mock_api.set_local_file_metadata(path("content/hero.uasset"), FileMetadata::new(150, 2));
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        mock_api.mark_for_edit(&path("content/level.umap")).await.unwrap();
+        mock_api.mark_for_delete(&path("content/prop.uasset")).await.unwrap();
+        mock_api.set_local_file_metadata(path("content/new.uasset"), FileMetadata::new(500, 2));
+        mock_api.mark_for_add(&path("content/new.uasset")).await.unwrap();
+
+        let unchanged_only = RevertOptions {
+            unchanged_only: true,
+            ..Default::default()
+        };
+        let reverted = mock_api.revert(&[path("content")], unchanged_only).await.unwrap();
+        assert_eq!(
+            reverted.len(),
+            1,
+            "Only files opened without changes should be reverted"
+        );
+        assert_eq!(reverted[0].path, path("content/level.umap"));
+        assert_eq!(reverted[0].reverted_change_state, ChangeState::Modified);
+        assert_eq!(
+            file_info(reverted[0].entry.as_ref().unwrap()),
+            (FileMetadata::new(200, 1), ChangeState::Unchanged, ConflictState::None)
+        );
+
+        let keep_local_content = RevertOptions {
+            keep_local_content: true,
+            ..Default::default()
+        };
+        let reverted = mock_api
+            .revert(&[path("content/hero.uasset")], keep_local_content)
+            .await
+            .unwrap();
+        assert_eq!(
+            file_info(reverted[0].entry.as_ref().unwrap()),
+            (FileMetadata::new(150, 2), ChangeState::Unchanged, ConflictState::None),
+            "Local content should be kept"
+        );
+
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        let reverted = mock_api
+            .revert(
+                &[path("content"), path("content/prop.uasset")],
+                RevertOptions::default(),
+            )
+            .await
+            .unwrap();
+        let reverted_paths = reverted
+            .iter()
+            .map(|file| (file.path.to_string(), file.reverted_change_state))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reverted_paths,
+            vec![
+                ("content/hero.uasset".to_string(), ChangeState::Modified),
+                ("content/new.uasset".to_string(), ChangeState::Added),
+                ("content/prop.uasset".to_string(), ChangeState::Deleted),
+            ]
+        );
+        assert_eq!(
+            file_info(reverted[0].entry.as_ref().unwrap()),
+            (FileMetadata::new(100, 1), ChangeState::Unchanged, ConflictState::None),
+            "The base metadata should be restored"
+        );
+        assert!(reverted[1].entry.is_none(), "Added files should be removed");
+
+        let content = mock_api
+            .fetch_directory(&path("content"), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        let names = content.entries().iter().map(|entry| entry.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["hero.uasset", "level.umap", "prop.uasset"]);
+        assert_eq!(content.change_states(), ChangeState::Unchanged);
+        assert_eq!(content.change_state_counts().get(ChangeState::Unchanged), 3);
+        let root = mock_api
+            .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(root.change_states(), ChangeState::Unchanged);
+
+        // Reverting an add while keeping local content leaves the file ready to be added again
+        mock_api.set_local_file_metadata(path("content/new.uasset"), FileMetadata::new(500, 2));
+        mock_api.mark_for_add(&path("content/new.uasset")).await.unwrap();
+        let keep_local_content = RevertOptions {
+            keep_local_content: true,
+            ..Default::default()
+        };
+        mock_api
+            .revert(&[path("content/new.uasset")], keep_local_content)
+            .await
+            .unwrap();
+        mock_api.mark_for_add(&path("content/new.uasset")).await.unwrap();
+
+        let reverted = mock_api
+            .revert(&[path("readme.txt")], RevertOptions::default())
+            .await
+            .unwrap();
+        assert!(reverted.is_empty(), "Paths without pending changes should be skipped");
+        let result = mock_api.revert(&[path("missing.txt")], RevertOptions::default()).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_conflicts() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![
+                        new_file_with_states("hero.uasset", ChangeState::Modified, ConflictState::None),
+                        new_file_with_states("level.umap", ChangeState::Modified, ConflictState::None),
+                        new_file_with_states("prop.uasset", ChangeState::Modified, ConflictState::None),
+                    ],
+                ),
+                new_file("readme.txt"),
+            ],
+        ));
+        let theirs_metadata = FileMetadata::new(1234, 1_700_000_000_000);
+        for conflict in [
+            new_conflict(
+                "content/prop.uasset",
+                ConflictKind::Rename {
+                    theirs_path: path("content/props/prop.uasset"),
+                },
+                Some(theirs_metadata.clone()),
+            ),
+            new_conflict(
+                "content/hero.uasset",
+                ConflictKind::Content,
+                Some(theirs_metadata.clone()),
+            ),
+            new_conflict("content/level.umap", ConflictKind::DeleteVsEdit, None),
+        ] {
+            mock_api.apply_mutation(MockMutation::AddConflict(conflict)).unwrap();
+        }
+
+        let conflicts = mock_api.list_conflicts(&RelativePath::default()).await.unwrap();
+        let conflict_paths = conflicts.iter().map(|info| info.path.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            conflict_paths,
+            vec!["content/hero.uasset", "content/level.umap", "content/prop.uasset"]
+        );
+        assert_eq!(conflicts[1].kind, ConflictKind::DeleteVsEdit);
+        assert_eq!(conflicts[1].published_by, "alice");
+        let content = mock_api
+            .fetch_directory(&path("content"), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(content.conflict_states(), ConflictState::Unresolved);
+
+        let entry = mock_api
+            .resolve_conflict(&path("content/hero.uasset"), Resolution::AcceptTheirs)
+            .await
+            .unwrap();
+        assert_eq!(
+            file_info(&entry),
+            (theirs_metadata, ChangeState::Modified, ConflictState::Resolved)
+        );
+        let result = mock_api
+            .resolve_conflict(&path("content/hero.uasset"), Resolution::AcceptYours)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotConflicted(_))));
+
+        let entry = mock_api
+            .resolve_conflict(&path("content/level.umap"), Resolution::AcceptTheirs)
+            .await
+            .unwrap();
+        let (_, change_state, _) = file_info(&entry);
+        assert_eq!(
+            change_state,
+            ChangeState::Deleted,
+            "Accepting their delete should open the file for delete"
+        );
+
+        let entry = mock_api
+            .resolve_conflict(&path("content/prop.uasset"), Resolution::Merged(b"merged".to_vec()))
+            .await
+            .unwrap();
+        let (metadata, _, conflict_state) = file_info(&entry);
+        assert_eq!(metadata.size_bytes(), 6);
+        assert_eq!(conflict_state, ConflictState::Resolved);
+
+        let result = mock_api
+            .resolve_conflict(&path("readme.txt"), Resolution::AcceptYours)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotConflicted(_))));
+
+        let conflicts = mock_api.list_conflicts(&path("content")).await.unwrap();
+        assert!(
+            conflicts
+                .iter()
+                .all(|info| info.conflict_state == ConflictState::Resolved)
+        );
+        let content = mock_api
+            .fetch_directory(&path("content"), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(content.conflict_states(), ConflictState::Resolved);
+
+        mock_api
+            .submit(SubmitTarget::Paths(vec![path("content")]), "Resolve conflicts")
+            .await
+            .unwrap();
+        let conflicts = mock_api.list_conflicts(&RelativePath::default()).await.unwrap();
+        assert!(conflicts.is_empty(), "Submitted files should have no conflicts");
+    }
+
+    #[tokio::test]
+    async fn test_file_history() {
+        let mut mock_api = MockWorkspaceApi::default();
+        mock_api
+            .set_directory_tree_from_json_str(include_str!("test_data/lyra.json"))
+            .await
+            .unwrap();
+        mock_api
+            .set_history_from_json_str(include_str!("test_data/lyra_history.json"))
+            .await
+            .expect("Setting history from JSON should succeed");
+
+        let history = mock_api
+            .file_history(&path("Lyra.uproject"), HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            revision_numbers(&history),
+            vec![7, 4, 1],
+            "History should be newest first"
+        );
+        assert_eq!(history[0].author, "dave");
+        assert_eq!(history[0].action, FileAction::Edit);
+        assert_eq!(history[2].action, FileAction::Add);
+        assert_eq!(history[0].metadata.as_ref().unwrap().size_bytes(), 5170);
+
+        let limited = HistoryOptions {
+            limit: NonZeroU32::new(2),
+            ..Default::default()
+        };
+        let history = mock_api.file_history(&path("Lyra.uproject"), limited).await.unwrap();
+        assert_eq!(revision_numbers(&history), vec![7, 4]);
+
+        let tags_path = path("Source/LyraGame/LyraGameplayTags.cpp");
+        let history = mock_api
+            .file_history(&tags_path, HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(revision_numbers(&history), vec![5, 3], "History should stop at a move");
+        let follow_renames = HistoryOptions {
+            follow_renames: true,
+            ..Default::default()
+        };
+        let history = mock_api.file_history(&tags_path, follow_renames.clone()).await.unwrap();
+        assert_eq!(revision_numbers(&history), vec![5, 3, 1]);
+        assert_eq!(history[2].path, path("Source/LyraGame/GameplayTags.cpp"));
+
+        // Deleted files are no longer in the workspace, but still have history
+        let history = mock_api
+            .file_history(&path("Config/DefaultOnline.ini"), HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(revision_numbers(&history), vec![4, 1]);
+        assert_eq!(history[0].action, FileAction::Delete);
+        assert!(history[0].metadata.is_none());
+
+        let result = mock_api
+            .file_history(&path("Config/Missing.ini"), HistoryOptions::default())
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let result = mock_api.file_history(&path("Config"), HistoryOptions::default()).await;
+        assert!(matches!(result, Err(WorkspaceApiError::IsADirectory(_))));
+
+        // Submitting records history, continuing from the newest revision in the fixture
+        mock_api.set_user_name("erin");
+        mock_api
+            .mark_for_move(&path("Lyra.uproject"), &path("LyraStarterGame.uproject"))
+            .await
+            .unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Changelist(DEFAULT_CHANGELIST.into()), "Rename project")
+            .await
+            .unwrap();
+        assert_eq!(revision, Revision::new(8));
+
+        let history = mock_api
+            .file_history(&path("LyraStarterGame.uproject"), follow_renames)
+            .await
+            .unwrap();
+        assert_eq!(revision_numbers(&history), vec![8, 7, 4, 1]);
+        assert_eq!(history[0].author, "erin");
+        assert_eq!(history[0].description, "Rename project");
+        assert_eq!(
+            history[0].action,
+            FileAction::Move {
+                from: path("Lyra.uproject")
+            }
+        );
+        let history = mock_api
+            .file_history(&path("Lyra.uproject"), HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(history[0].action, FileAction::Delete);
+    }
+
+    #[tokio::test]
+    async fn test_changesets() {
+        let mut mock_api = MockWorkspaceApi::default();
+        mock_api
+            .set_directory_tree_from_json_str(include_str!("test_data/lyra.json"))
+            .await
+            .unwrap();
+        mock_api
+            .set_history_from_json_str(include_str!("test_data/lyra_history.json"))
+            .await
+            .unwrap();
+
+        let changeset = mock_api.fetch_changeset(Revision::new(3)).await.unwrap();
+        assert_eq!(changeset.author, "carol");
+        assert_eq!(
+            changeset.changes,
+            vec![
+                ChangesetChange {
+                    path: path("Source/LyraGame/GameplayTags.cpp"),
+                    change_state: ChangeState::Deleted,
+                },
+                ChangesetChange {
+                    path: path("Source/LyraGame/LyraGameplayTags.cpp"),
+                    change_state: ChangeState::Added,
+                },
+            ],
+            "A move should be a delete and an add"
+        );
+        let result = mock_api.fetch_changeset(Revision::new(99)).await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+
+        let changesets = mock_api.list_changesets(.., None).await.unwrap();
+        assert_eq!(changeset_ids(&changesets), vec![7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(changesets[6].changes.len(), 5, "Changesets should include every change");
+
+        let changesets = mock_api
+            .list_changesets(Revision::new(2)..=Revision::new(5), Some(&path("Config")))
+            .await
+            .unwrap();
+        assert_eq!(changeset_ids(&changesets), vec![4, 2]);
+        assert_eq!(
+            changesets[0].changes.len(),
+            2,
+            "Path filtered changesets should still include every change"
+        );
+
+        mock_api.mark_for_edit(&path("README.md")).await.unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![path("README.md")]), "Update readme")
+            .await
+            .unwrap();
+        let changesets = mock_api.list_changesets(revision.., None).await.unwrap();
+        assert_eq!(changesets.len(), 1);
+        assert_eq!(changesets[0].author, DEFAULT_USER_NAME);
+        assert_eq!(changesets[0].description, "Update readme");
+
+        let json = serde_json::to_string(&changesets[0]).unwrap();
+        let round_tripped: Changeset = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped.id, revision);
+        assert_eq!(round_tripped.changes, changesets[0].changes);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_at_revision() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry(
+                "content",
+                vec![
+                    new_file_with_states("hero.uasset", ChangeState::Modified, ConflictState::None),
+                    new_file("level.umap"),
+                    new_file("prop.uasset"),
+                ],
+            )],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("hero.uasset")])]),
+        });
+        mock_api.add_snapshot(MockSnapshot {
+            name: "levels".to_string(),
+            revision: Revision::new(3),
+            submitted_time_unix_ms_utc: 3000,
+            tree: new_directory(
+                "",
+                vec![new_directory_entry(
+                    "content",
+                    vec![new_file("hero.uasset"), new_file("level.umap")],
+                )],
+            ),
+        });
+
+        let names = fetch_names(&mock_api, "", at(RevisionSelector::Revision(Revision::new(2)))).await;
+        assert_eq!(
+            names,
+            vec!["content", "content/hero.uasset"],
+            "Revisions between snapshots should be served the earlier snapshot"
+        );
+        let names = fetch_names(&mock_api, "content", at(RevisionSelector::Revision(Revision::new(3)))).await;
+        assert_eq!(names, vec!["content/hero.uasset", "content/level.umap"]);
+        let names = fetch_names(&mock_api, "", at(RevisionSelector::Timestamp(2500))).await;
+        assert_eq!(names, vec!["content", "content/hero.uasset"]);
+        let names = fetch_names(&mock_api, "", at(RevisionSelector::Timestamp(500))).await;
+        assert!(names.is_empty(), "The depot should be empty before the first revision");
+        let names = fetch_names(&mock_api, "content", DirectoryFetchOptions::default()).await;
+        assert_eq!(
+            names,
+            vec!["content/hero.uasset", "content/level.umap", "content/prop.uasset"],
+            "The workspace should be served without a revision"
+        );
+
+        let result = mock_api
+            .fetch_directory(&path("content"), at(RevisionSelector::Timestamp(500)))
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let result = mock_api
+            .fetch_directory(&path("content"), at(RevisionSelector::Revision(Revision::new(4))))
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+
+        let directories = mock_api
+            .fetch_directory_stream(
+                &RelativePath::default(),
+                at(RevisionSelector::Revision(Revision::new(1))),
+            )
+            .map(|result| result.unwrap().relative_path().to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(directories, vec!["", "content"]);
+        let page = mock_api
+            .fetch_directory_page(&path("content"), at(RevisionSelector::Revision(Revision::new(3))))
+            .await
+            .unwrap();
+        assert_eq!(page.directory.entries().len(), 2);
+        assert_eq!(
+            page.directory.change_states(),
+            ChangeState::Unchanged,
+            "Historical trees should have no pending changes"
+        );
+        let result = mock_api
+            .fetch_entry(
+                &path("content/level.umap"),
+                EntryFetchOptions {
+                    revision: Some(RevisionSelector::Revision(Revision::new(1))),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(
+            matches!(result, Err(WorkspaceApiError::NotFound(_))),
+            "The entry should be fetched at the selected revision"
+        );
+        let entry = mock_api
+            .fetch_entry(
+                &path("content"),
+                EntryFetchOptions {
+                    revision: Some(RevisionSelector::Revision(Revision::new(1))),
+                    directory_options: Some(DirectoryFetchOptions::default()),
+                },
+            )
+            .await
+            .unwrap();
+        let DirectoryEntryType::Directory(Some(directory)) = entry.info() else {
+            panic!("Directory should be loaded when directory options are given");
+        };
+        assert_eq!(
+            directory.files().map(|(path, _)| path.to_string()).collect::<Vec<_>>(),
+            vec!["content/hero.uasset"],
+            "Loaded directories should be fetched at the selected revision"
+        );
+
+        // Snapshots with the same name replace each other
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("prop.uasset")])]),
+        });
+        let names = fetch_names(&mock_api, "", at(RevisionSelector::Revision(Revision::new(1)))).await;
+        assert_eq!(names, vec!["content", "content/prop.uasset"]);
+
+        // Timestamps select the newest revision submitted by then, including revisions known only from the history
+        mock_api.set_history(vec![FileRevision {
+            revision: Revision::new(3),
+            path: path("content/level.umap"),
+            author: "alice".to_string(),
+            submitted_time_unix_ms_utc: 2000,
+            description: "Add level".to_string(),
+            action: FileAction::Add,
+            metadata: Some(FileMetadata::new(0, 0)),
+        }]);
+        let names = fetch_names(&mock_api, "content", at(RevisionSelector::Timestamp(2500))).await;
+        assert_eq!(names, vec!["content/hero.uasset", "content/level.umap"]);
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "content",
+                    vec![new_file("hero.uasset"), new_file("level.umap"), new_file("prop.uasset")],
+                ),
+                new_file("readme.txt"),
+            ],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("hero.uasset")])]),
+        });
+
+        mock_api.set_local_file_metadata(path("content/hero.uasset"), FileMetadata::new(5, 1));
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        mock_api.mark_for_edit(&path("readme.txt")).await.unwrap();
+        mock_api.mark_for_delete(&path("content/prop.uasset")).await.unwrap();
+        mock_api.set_local_file_metadata(path("build/game.pak"), FileMetadata::new(3, 1));
+        mock_api.mark_for_add(&path("build/game.pak")).await.unwrap();
+
+        let diff = mock_api
+            .diff_trees(TreeVersion::Base, TreeVersion::Workspace, &RelativePath::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            diff_changes(&diff),
+            vec![
+                ("build/game.pak".to_string(), ChangeState::Added),
+                ("content/hero.uasset".to_string(), ChangeState::Modified),
+                ("content/prop.uasset".to_string(), ChangeState::Deleted),
+            ],
+            "Files opened without changes should not differ"
+        );
+        assert_eq!(
+            file_info(
+                diff.find_directory(&path("content"))
+                    .unwrap()
+                    .entry("hero.uasset")
+                    .unwrap()
+            )
+            .0,
+            FileMetadata::new(5, 1)
+        );
+
+        let diff = mock_api
+            .diff_trees(TreeVersion::Workspace, TreeVersion::Base, &path("content"))
+            .await
+            .unwrap();
+        assert_eq!(
+            diff_changes(&diff),
+            vec![
+                ("content/hero.uasset".to_string(), ChangeState::Modified),
+                ("content/prop.uasset".to_string(), ChangeState::Added),
+            ]
+        );
+        assert_eq!(diff.relative_path(), &path("content"));
+
+        let diff = mock_api
+            .diff_trees(
+                TreeVersion::Revision(RevisionSelector::Revision(Revision::new(1))),
+                TreeVersion::Base,
+                &path("content"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            diff_changes(&diff),
+            vec![
+                ("content/level.umap".to_string(), ChangeState::Added),
+                ("content/prop.uasset".to_string(), ChangeState::Added),
+            ]
+        );
+
+        // A scope missing from one version is entirely added or deleted
+        let mut diff = mock_api
+            .diff_trees(TreeVersion::Workspace, TreeVersion::Base, &path("build"))
+            .await
+            .unwrap();
+        assert_eq!(
+            diff_changes(&diff),
+            vec![("build/game.pak".to_string(), ChangeState::Deleted)]
+        );
+        diff.prune_to_depth(0);
+        assert_eq!(diff.change_state_counts().get(ChangeState::Deleted), 1);
+
+        let result = mock_api
+            .diff_trees(TreeVersion::Base, TreeVersion::Workspace, &path("missing"))
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_diff_file() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_file("readme.txt"), new_file("hero.uasset")],
+        ));
+        mock_api.set_file_content(path("readme.txt"), FileMetadata::new(0, 0), "one\ntwo\nthree\n");
+        mock_api.set_file_content(path("hero.uasset"), FileMetadata::new(0, 0), vec![0u8, 1, 2]);
+
+        mock_api.set_local_file_metadata(path("readme.txt"), FileMetadata::new(14, 1));
+        mock_api.set_file_content(path("readme.txt"), FileMetadata::new(14, 1), "one\n2\nthree\n");
+        mock_api.mark_for_edit(&path("readme.txt")).await.unwrap();
+        mock_api.set_local_file_metadata(path("notes.txt"), FileMetadata::new(6, 1));
+        mock_api.set_file_content(path("notes.txt"), FileMetadata::new(6, 1), "notes\n");
+        mock_api.mark_for_add(&path("notes.txt")).await.unwrap();
+
+        let diff = mock_api
+            .diff_file(&path("readme.txt"), TreeVersion::Base, TreeVersion::Workspace)
+            .await
+            .unwrap();
+        assert!(diff.differs && !diff.is_binary);
+        assert_eq!(
+            diff.to_unified(),
+            "--- a/readme.txt\n+++ b/readme.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+
+        let diff = mock_api
+            .diff_file(&path("notes.txt"), TreeVersion::Base, TreeVersion::Workspace)
+            .await
+            .unwrap();
+        assert!(diff.from_missing && !diff.to_missing);
+        assert_eq!(
+            diff.to_unified(),
+            "--- /dev/null\n+++ b/notes.txt\n@@ -0,0 +1,1 @@\n+notes\n"
+        );
+
+        let diff = mock_api
+            .diff_file(&path("hero.uasset"), TreeVersion::Base, TreeVersion::Workspace)
+            .await
+            .unwrap();
+        assert!(!diff.differs && diff.is_binary);
+        assert_eq!(diff.to_unified(), "");
+
+        let result = mock_api
+            .diff_file(&path("missing.txt"), TreeVersion::Base, TreeVersion::Workspace)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+
+        // Content which was never captured is not found, even though the file exists in both versions
+        mock_api.set_local_file_metadata(path("hero.uasset"), FileMetadata::new(9, 1));
+        mock_api.mark_for_edit(&path("hero.uasset")).await.unwrap();
+        let result = mock_api
+            .diff_file(&path("hero.uasset"), TreeVersion::Base, TreeVersion::Workspace)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(missing)) if missing == path("hero.uasset")));
+        let result = mock_api
+            .diff_file(&path("hero.uasset"), TreeVersion::Workspace, TreeVersion::Base)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_locks() {
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry("content", vec![new_file("hero.uasset"), new_file("level.umap")]),
+                new_file("readme.txt"),
+            ],
+        ));
+        let watch = mock_api.watch(&path("content"), true);
+
+        // Simulate an artist on another machine checking out the level
+        mock_api
+            .apply_mutation(MockMutation::SetLock {
+                path: path("content/level.umap"),
+                owner: Some("bob".to_string()),
+            })
+            .unwrap();
+        for result in [
+            mock_api.lock(&path("content/level.umap")).await,
+            mock_api.unlock(&path("content/level.umap")).await,
+            mock_api.mark_for_edit(&path("content/level.umap")).await,
+            mock_api.mark_for_delete(&path("content/level.umap")).await,
+            mock_api
+                .mark_for_move(&path("content/level.umap"), &path("content/moved.umap"))
+                .await,
+        ] {
+            let Err(WorkspaceApiError::LockedByOther(locked_path, owner)) = result else {
+                panic!("Files locked by another user should not be changeable");
+            };
+            assert_eq!(locked_path, path("content/level.umap"));
+            assert_eq!(owner, "bob");
+        }
+
+        let entry = mock_api.lock(&path("content/hero.uasset")).await.unwrap();
+        assert_eq!(lock_info(&entry), Lock::LockedByYou);
+        mock_api
+            .lock(&path("content/hero.uasset"))
+            .await
+            .expect("Locking a file already locked by the current user should succeed");
+
+        let root = mock_api
+            .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            root.lock_states(),
+            LockState::Unlocked | LockState::LockedByYou | LockState::LockedByOther
+        );
+        let content = root.find_directory(&path("content")).unwrap();
+        assert_eq!(content.lock_state_counts().get(LockState::LockedByOther), 1);
+        assert_eq!(content.lock_state_counts().get(LockState::LockedByYou), 1);
+        assert_eq!(content.lock_state_counts().get(LockState::Unlocked), 0);
+
+        assert_eq!(
+            mock_api.list_locks(&RelativePath::default()).await.unwrap(),
+            vec![
+                FileLock {
+                    path: path("content/hero.uasset"),
+                    lock: Lock::LockedByYou,
+                },
+                FileLock {
+                    path: path("content/level.umap"),
+                    lock: Lock::LockedByOther {
+                        owner: "bob".to_string()
+                    },
+                },
+            ]
+        );
+        assert!(mock_api.list_locks(&path("readme.txt")).await.unwrap().is_empty());
+        let result = mock_api.list_locks(&path("missing")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+
+        let result = mock_api.unlock(&path("readme.txt")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotLocked(_))));
+        let result = mock_api.lock(&path("content")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::IsADirectory(_))));
+
+        // Submitting releases the lock
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        mock_api
+            .submit(SubmitTarget::Paths(vec![path("content/hero.uasset")]), "Hero pose")
+            .await
+            .unwrap();
+        let entry = mock_api
+            .fetch_entry(&path("content/hero.uasset"), EntryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(lock_info(&entry), Lock::Unlocked);
+
+        mock_api
+            .apply_mutation(MockMutation::SetLock {
+                path: path("content/level.umap"),
+                owner: None,
+            })
+            .unwrap();
+        mock_api.lock(&path("content/level.umap")).await.unwrap();
+        mock_api.unlock(&path("content/level.umap")).await.unwrap();
+
+        let events = watch
+            .take(5)
+            .map(|event| {
+                let event = event.unwrap();
+                (event.kind, event.path.to_string())
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                (WatchEventKind::LockStateChanged, "content/level.umap".to_string()),
+                (WatchEventKind::LockStateChanged, "content/hero.uasset".to_string()),
+                (WatchEventKind::ChangeStateChanged, "content/hero.uasset".to_string()),
+                (WatchEventKind::ChangeStateChanged, "content/hero.uasset".to_string()),
+                (WatchEventKind::LockStateChanged, "content/hero.uasset".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streams() {
+        let root_files = async |mock_api: &MockWorkspaceApi| {
+            let root = mock_api
+                .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+                .await
+                .unwrap();
+            root.files()
+                .map(|(path, entry)| (path.to_string(), file_info(entry).0))
+                .collect::<Vec<_>>()
+        };
+        let mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry("content", vec![new_file("hero.uasset")])],
+        ));
+
+        let main = mock_api.current_stream().await.unwrap();
+        assert_eq!(
+            main,
+            Stream {
+                name: DEFAULT_STREAM_NAME.to_string(),
+                parent: None,
+                stream_type: StreamType::Mainline,
+                head_revision: Revision::new(0),
+            }
+        );
+
+        // Pending changes are not branched into the new stream
+        mock_api.set_local_file_metadata(path("content/hero.uasset"), FileMetadata::new(5, 1));
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        let dev = mock_api
+            .create_stream("dev", DEFAULT_STREAM_NAME, StreamType::Development)
+            .await
+            .unwrap();
+        assert_eq!(dev.parent.as_deref(), Some(DEFAULT_STREAM_NAME));
+        assert_eq!(dev.head_revision, Revision::new(0));
+        assert_eq!(mock_api.current_stream().await.unwrap(), main);
+
+        let result = mock_api
+            .create_stream("dev", DEFAULT_STREAM_NAME, StreamType::Release)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::StreamAlreadyExists(_))));
+        let result = mock_api.create_stream("release", "missing", StreamType::Release).await;
+        assert!(matches!(result, Err(WorkspaceApiError::StreamNotFound(_))));
+        let result = mock_api
+            .create_stream("other", DEFAULT_STREAM_NAME, StreamType::Mainline)
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::InvalidStreamType(_))));
+
+        let result = mock_api.switch_stream("dev").await;
+        let Err(WorkspaceApiError::PendingChanges(paths)) = result else {
+            panic!("Switching with pending changes should fail");
+        };
+        assert_eq!(paths, vec![path("content/hero.uasset")]);
+
+        mock_api
+            .submit(SubmitTarget::Paths(vec![path("content/hero.uasset")]), "Main change")
+            .await
+            .unwrap();
+        assert_eq!(mock_api.switch_stream("dev").await.unwrap().name, "dev");
+        assert_eq!(mock_api.current_stream().await.unwrap().name, "dev");
+        assert_eq!(
+            root_files(&mock_api).await,
+            vec![("content/hero.uasset".to_string(), FileMetadata::new(0, 0))],
+            "The dev stream should not have changes submitted to main after it was branched"
+        );
+
+        mock_api.set_local_file_metadata(path("dev.txt"), FileMetadata::new(3, 1));
+        mock_api.mark_for_add(&path("dev.txt")).await.unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![path("dev.txt")]), "Dev change")
+            .await
+            .unwrap();
+        assert_eq!(mock_api.current_stream().await.unwrap().head_revision, revision);
+
+        mock_api.switch_stream(DEFAULT_STREAM_NAME).await.unwrap();
+        assert_eq!(
+            root_files(&mock_api).await,
+            vec![("content/hero.uasset".to_string(), FileMetadata::new(5, 1))]
+        );
+        let streams = mock_api.list_streams().await.unwrap();
+        assert_eq!(streams[0].head_revision, revision);
+        assert_eq!(streams[1].head_revision, Revision::new(1));
+        assert_eq!(stream_names(streams), vec!["dev", DEFAULT_STREAM_NAME]);
+
+        mock_api
+            .switch_stream(DEFAULT_STREAM_NAME)
+            .await
+            .expect("Switching to the current stream should succeed");
+        let result = mock_api.switch_stream("missing").await;
+        assert!(matches!(result, Err(WorkspaceApiError::StreamNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stream_history() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry("content", vec![new_file("hero.uasset")])],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("hero.uasset")])]),
+        });
+        mock_api
+            .create_label("initial", LabelSource::Workspace, &RelativePath::default(), "")
+            .await
+            .unwrap();
+
+        // The dev stream branches the history of main, but not its labels
+        mock_api
+            .create_stream("dev", DEFAULT_STREAM_NAME, StreamType::Development)
+            .await
+            .unwrap();
+        mock_api.switch_stream("dev").await.unwrap();
+        assert!(mock_api.list_labels().await.unwrap().is_empty());
+        let root = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Revision(Revision::new(1))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(file_paths(&root), vec!["content/hero.uasset"]);
+
+        mock_api.set_local_file_metadata(path("dev.txt"), FileMetadata::new(3, 1));
+        mock_api.mark_for_add(&path("dev.txt")).await.unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![path("dev.txt")]), "Dev change")
+            .await
+            .unwrap();
+        assert_eq!(revision, Revision::new(2));
+        assert_eq!(
+            mock_api.fetch_changeset(revision).await.unwrap().description,
+            "Dev change"
+        );
+
+        // Main has no revisions after the branch, so the revision submitted to dev can't be fetched there
+        mock_api.switch_stream(DEFAULT_STREAM_NAME).await.unwrap();
+        let result = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Revision(Revision::new(2))),
+            )
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+        let result = mock_api.fetch_changeset(revision).await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+        let result = mock_api.file_history(&path("dev.txt"), HistoryOptions::default()).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let root = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Revision(Revision::new(1))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(file_paths(&root), vec!["content/hero.uasset"]);
+        let labels = mock_api.list_labels().await.unwrap();
+        assert_eq!(
+            labels.into_iter().map(|label| label.name).collect::<Vec<_>>(),
+            vec!["initial"]
+        );
+
+        // Switching back restores the history of dev
+        mock_api.switch_stream("dev").await.unwrap();
+        let history = mock_api
+            .file_history(&path("dev.txt"), HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].revision, revision);
+    }
+
+    #[tokio::test]
+    async fn test_labels() {
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![
+                new_directory_entry("content", vec![new_file("hero.uasset"), new_file("level.umap")]),
+                new_directory_entry("docs", vec![new_file("readme.txt")]),
+            ],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("hero.uasset")])]),
+        });
+
+        // Pending changes are not labeled, and the workspace is behind the snapshot until it is synced
+        mock_api.set_local_file_metadata(path("content/hero.uasset"), FileMetadata::new(5, 1));
+        mock_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        let label = mock_api
+            .create_label("workspace", LabelSource::Workspace, &path("content"), "Shipped content")
+            .await
+            .unwrap();
+        assert_eq!(label.scope, path("content"));
+        assert_eq!(label.revision, Revision::new(0));
+        assert_eq!(label.owner, DEFAULT_USER_NAME);
+        assert_eq!(label.description, "Shipped content");
+
+        let labeled = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Label("workspace".to_string())),
+            )
+            .await
+            .unwrap();
+        let unchanged = (FileMetadata::new(0, 0), ChangeState::Unchanged, ConflictState::None);
+        assert_eq!(
+            file_infos(&labeled),
+            vec![
+                ("content/hero.uasset".to_string(), unchanged.clone()),
+                ("content/level.umap".to_string(), unchanged.clone()),
+            ]
+        );
+        let result = mock_api
+            .fetch_directory(&path("docs"), at(RevisionSelector::Label("workspace".to_string())))
+            .await;
+        assert!(
+            matches!(result, Err(WorkspaceApiError::NotFound(_))),
+            "Paths outside the label's scope should not be found"
+        );
+
+        let label = mock_api
+            .create_label(
+                "release-1.0",
+                LabelSource::Revision(RevisionSelector::Timestamp(1500)),
+                &RelativePath::default(),
+                "Release 1.0",
+            )
+            .await
+            .unwrap();
+        assert_eq!(label.revision, Revision::new(1));
+        let labeled = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Label("release-1.0".to_string())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            file_infos(&labeled),
+            vec![("content/hero.uasset".to_string(), unchanged)]
+        );
+        let label = mock_api
+            .create_label(
+                "release-1.0-copy",
+                LabelSource::Revision(RevisionSelector::Label("release-1.0".to_string())),
+                &path("content/hero.uasset"),
+                "Copy",
+            )
+            .await
+            .unwrap();
+        assert_eq!(label.revision, Revision::new(1));
+
+        let result = mock_api
+            .create_label("workspace", LabelSource::Workspace, &RelativePath::default(), "")
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::LabelAlreadyExists(_))));
+        let result = mock_api
+            .create_label("missing", LabelSource::Workspace, &path("missing"), "")
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let result = mock_api
+            .create_label(
+                "future",
+                LabelSource::Revision(RevisionSelector::Revision(Revision::new(2))),
+                &RelativePath::default(),
+                "",
+            )
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+
+        let labels = mock_api.list_labels().await.unwrap();
+        let names = labels.iter().map(|label| label.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["release-1.0", "release-1.0-copy", "workspace"]);
+        let json = serde_json::to_string(&labels).unwrap();
+        let round_tripped: Vec<Label> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, labels);
+
+        mock_api.delete_label("release-1.0").await.unwrap();
+        let result = mock_api.delete_label("release-1.0").await;
+        assert!(matches!(result, Err(WorkspaceApiError::LabelNotFound(_))));
+        let result = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Label("release-1.0".to_string())),
+            )
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::LabelNotFound(_))));
+        assert_eq!(mock_api.list_labels().await.unwrap().len(), 2);
+
+        // Syncing only part of the workspace leaves it behind, syncing all of it brings it to the head revision
+        mock_api
+            .sync(&path("docs"), None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        let label = mock_api
+            .create_label("partly-synced", LabelSource::Workspace, &RelativePath::default(), "")
+            .await
+            .unwrap();
+        assert_eq!(label.revision, Revision::new(0));
+        mock_api
+            .sync(&RelativePath::default(), None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        let label = mock_api
+            .create_label("synced", LabelSource::Workspace, &RelativePath::default(), "")
+            .await
+            .unwrap();
+        assert_eq!(label.revision, Revision::new(1));
+        let labeled = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                at(RevisionSelector::Label("synced".to_string())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            file_infos(&labeled),
+            vec![(
+                "content/hero.uasset".to_string(),
+                (FileMetadata::new(0, 0), ChangeState::Unchanged, ConflictState::None)
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shelves() {
+        let new_tree = || {
+            new_directory(
+                "",
+                vec![
+                    new_directory_entry("content", vec![new_file("hero.uasset"), new_file("level.umap")]),
+                    new_file("readme.txt"),
+                ],
+            )
+        };
+        let mut author_api = MockWorkspaceApi::with_directory_tree(new_tree());
+        author_api.set_user_name("alice");
+        let mut reviewer_api = MockWorkspaceApi::with_directory_tree(new_tree());
+        reviewer_api.set_user_name("bob");
+        reviewer_api.set_shelf_store(author_api.shelf_store());
+
+        author_api.set_local_file_metadata(path("content/hero.uasset"), FileMetadata::new(5, 1));
+        author_api.mark_for_edit(&path("content/hero.uasset")).await.unwrap();
+        author_api.set_local_file_metadata(path("content/props/crate.uasset"), FileMetadata::new(3, 1));
+        author_api
+            .mark_for_add(&path("content/props/crate.uasset"))
+            .await
+            .unwrap();
+        author_api.mark_for_delete(&path("content/level.umap")).await.unwrap();
+        author_api
+            .move_to_changelist(
+                &[path("content/hero.uasset"), path("content/props/crate.uasset")],
+                "review",
+            )
+            .await
+            .unwrap();
+
+        let shelf = author_api.shelve("review", "Hero rework").await.unwrap();
+        assert_eq!(shelf.owner, "alice");
+        assert_eq!(shelf.changelist, "review");
+        assert_eq!(shelf.contents.change_state_counts().total(), 2);
+        let result = author_api.shelve("empty", "Nothing").await;
+        assert!(matches!(result, Err(WorkspaceApiError::NothingToShelve)));
+        let pending = author_api.list_pending_changes(&RelativePath::default()).await.unwrap();
+        assert_eq!(
+            pending.iter().map(|changelist| changelist.changes.len()).sum::<usize>(),
+            3,
+            "Shelved changes should stay pending"
+        );
+
+        // The reviewer sees the shelf through the shared store
+        let shelves = reviewer_api.list_shelves(None).await.unwrap();
+        assert_eq!(shelves.len(), 1);
+        let listed = &shelves[0].contents;
+        assert!(matches!(
+            listed.entries()[0].info(),
+            DirectoryEntryType::Directory(None)
+        ));
+        assert_eq!(listed.change_state_counts().get(ChangeState::Added), 1);
+        assert_eq!(listed.change_state_counts().get(ChangeState::Modified), 1);
+        assert!(reviewer_api.list_shelves(Some("bob")).await.unwrap().is_empty());
+        assert_eq!(reviewer_api.list_shelves(Some("alice")).await.unwrap().len(), 1);
+
+        let fetched = reviewer_api.fetch_shelf(shelf.id).await.unwrap();
+        assert_eq!(fetched.description, "Hero rework");
+        assert_eq!(fetched.contents.files().count(), 2);
+
+        let unshelved = reviewer_api.unshelve(shelf.id, "alice-review").await.unwrap();
+        let expected = vec![
+            (
+                "content/hero.uasset".to_string(),
+                ChangeState::Modified,
+                FileMetadata::new(5, 1),
+            ),
+            (
+                "content/props/crate.uasset".to_string(),
+                ChangeState::Added,
+                FileMetadata::new(3, 1),
+            ),
+        ];
+        assert_eq!(change_summary(&unshelved), expected);
+        let pending = reviewer_api
+            .list_pending_changes(&RelativePath::default())
+            .await
+            .unwrap();
+        let review = pending
+            .iter()
+            .find(|changelist| changelist.name == "alice-review")
+            .expect("Unshelved changes should be in the target changelist");
+        assert_eq!(change_summary(&review.changes), expected);
+
+        let result = reviewer_api.unshelve(shelf.id, "alice-review").await;
+        assert!(matches!(
+            result,
+            Err(WorkspaceApiError::AlreadyOpened(_, ChangeState::Modified))
+        ));
+        let result = reviewer_api.fetch_shelf(ShelfId::new(99)).await;
+        assert!(matches!(result, Err(WorkspaceApiError::ShelfNotFound(_))));
+        let result = reviewer_api.unshelve(ShelfId::new(99), DEFAULT_CHANGELIST).await;
+        assert!(matches!(result, Err(WorkspaceApiError::ShelfNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let empty_api = MockWorkspaceApi::with_directory_tree(new_directory("", vec![new_file("readme.txt")]));
+        let changes = empty_api.preview_sync(&RelativePath::default(), None).await.unwrap();
+        assert!(
+            changes.is_empty(),
+            "Without snapshots the workspace should be at the head"
+        );
+        assert_eq!(empty_api.sync(&RelativePath::default(), None).count().await, 0);
+
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry(
+                "content",
+                vec![
+                    new_file_with_states("hero.uasset", ChangeState::Modified, ConflictState::None),
+                    new_file("level.umap"),
+                    new_file_with_states("notes.txt", ChangeState::Modified, ConflictState::None),
+                    new_file("old.uasset"),
+                    new_file("prop.uasset"),
+                ],
+            )],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "incoming".to_string(),
+            revision: Revision::new(2),
+            submitted_time_unix_ms_utc: 2000,
+            tree: new_directory(
+                "",
+                vec![
+                    new_directory_entry(
+                        "content",
+                        vec![
+                            new_sized_file("hero.uasset", 100, 2000),
+                            new_sized_file("level.umap", 200, 2000),
+                            new_sized_file("new.uasset", 50, 2000),
+                            new_file("prop.uasset"),
+                        ],
+                    ),
+                    new_directory_entry("maps", vec![new_sized_file("city.umap", 300, 2000)]),
+                ],
+            ),
+        });
+
+        let incoming = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                DirectoryFetchOptions {
+                    conflict_state_filter: Some(ConflictState::Incoming.into()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            incoming.files().map(|(path, _)| path.to_string()).collect::<Vec<_>>(),
+            vec![
+                "content/hero.uasset",
+                "content/level.umap",
+                "content/notes.txt",
+                "content/old.uasset"
+            ],
+            "Files changed by the snapshot should be marked as incoming"
+        );
+
+        let changes = mock_api.preview_sync(&RelativePath::default(), None).await.unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![
+                ("content/hero.uasset".to_string(), SyncAction::Conflict),
+                ("content/level.umap".to_string(), SyncAction::Update),
+                ("content/new.uasset".to_string(), SyncAction::Add),
+                ("content/notes.txt".to_string(), SyncAction::Conflict),
+                ("content/old.uasset".to_string(), SyncAction::Delete),
+                ("maps/city.umap".to_string(), SyncAction::Add),
+            ]
+        );
+        assert_eq!(
+            changes[3].metadata, None,
+            "Deleted files should have no incoming metadata"
+        );
+        let entry = mock_api
+            .fetch_entry(&path("content/hero.uasset"), EntryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            file_info(&entry).2,
+            ConflictState::Incoming,
+            "Previewing should not change the workspace"
+        );
+
+        let changes = mock_api.preview_sync(&path("maps"), None).await.unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![("maps/city.umap".to_string(), SyncAction::Add)],
+            "A scope which is only in the target should be previewable"
+        );
+        let result = mock_api.preview_sync(&path("missing"), None).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let result = mock_api
+            .preview_sync(
+                &RelativePath::default(),
+                Some(RevisionSelector::Revision(Revision::new(3))),
+            )
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::RevisionNotFound(_))));
+
+        // Cancel the sync after the first two files by dropping the stream
+        let progress = mock_api
+            .sync(&RelativePath::default(), None)
+            .take(2)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(progress[1].files_done, 2);
+        assert_eq!(progress[1].files_total, 6);
+        assert_eq!(progress[1].bytes_done, 300);
+        assert_eq!(progress[1].bytes_total, 650);
+        let changes = mock_api.preview_sync(&RelativePath::default(), None).await.unwrap();
+        assert_eq!(
+            actions(&changes),
+            vec![
+                ("content/new.uasset".to_string(), SyncAction::Add),
+                ("content/notes.txt".to_string(), SyncAction::Conflict),
+                ("content/old.uasset".to_string(), SyncAction::Delete),
+                ("maps/city.umap".to_string(), SyncAction::Add),
+            ],
+            "Files synced before the sync was cancelled should stay synced"
+        );
+
+        let watch = mock_api.watch(&RelativePath::default(), true);
+        let progress = mock_api
+            .sync(&RelativePath::default(), None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            progress
+                .iter()
+                .map(|progress| (progress.files_done, progress.bytes_done))
+                .collect::<Vec<_>>(),
+            vec![(1, 50), (2, 50), (3, 50), (4, 350)]
+        );
+        assert!(progress.last().unwrap().is_complete());
+        let changes = mock_api.preview_sync(&RelativePath::default(), None).await.unwrap();
+        assert!(changes.is_empty(), "Nothing should be left to sync");
+
+        let names = fetch_names(&mock_api, "", DirectoryFetchOptions::default()).await;
+        assert_eq!(
+            names,
+            vec![
+                "content",
+                "content/hero.uasset",
+                "content/level.umap",
+                "content/new.uasset",
+                "content/notes.txt",
+                "content/prop.uasset",
+                "maps",
+                "maps/city.umap",
+            ]
+        );
+        let entry = mock_api
+            .fetch_entry(&path("content/level.umap"), EntryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            (file_info(&entry).0, file_info(&entry).2),
+            (FileMetadata::new(200, 2000), ConflictState::None),
+            "Synced files should no longer be incoming"
+        );
+
+        let conflicts = mock_api.list_conflicts(&RelativePath::default()).await.unwrap();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].path, path("content/hero.uasset"));
+        assert_eq!(conflicts[0].kind, ConflictKind::Content);
+        assert_eq!(conflicts[0].theirs_revision, Revision::new(2));
+        assert_eq!(conflicts[0].published_time_unix_ms_utc, 2000);
+        assert_eq!(conflicts[0].theirs_metadata, Some(FileMetadata::new(100, 2000)));
+        assert_eq!(conflicts[1].path, path("content/notes.txt"));
+        assert_eq!(conflicts[1].kind, ConflictKind::DeleteVsEdit);
+        let entry = mock_api
+            .fetch_entry(&path("content/notes.txt"), EntryFetchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            file_info(&entry).1,
+            ChangeState::Modified,
+            "Conflicting files should keep their pending changes"
+        );
+
+        let resolved = mock_api
+            .resolve_conflict(&path("content/hero.uasset"), Resolution::AcceptTheirs)
+            .await
+            .unwrap();
+        assert_eq!(file_info(&resolved).0, FileMetadata::new(100, 2000));
+        mock_api
+            .submit(SubmitTarget::Paths(vec![path("content/hero.uasset")]), "Merged hero")
+            .await
+            .unwrap();
+
+        let events = watch
+            .take(5)
+            .map(|event| {
+                let event = event.unwrap();
+                (event.kind, event.path.to_string())
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                (WatchEventKind::EntryAdded, "content/new.uasset".to_string()),
+                (WatchEventKind::ConflictStateChanged, "content/notes.txt".to_string()),
+                (WatchEventKind::EntryRemoved, "content/old.uasset".to_string()),
+                (WatchEventKind::EntryAdded, "maps".to_string()),
+                (WatchEventKind::EntryAdded, "maps/city.umap".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incoming_changes() {
+        let hero_path = path("content/hero.uasset");
+        let conflict_state = async |mock_api: &MockWorkspaceApi| {
+            let entry = mock_api
+                .fetch_entry(&path("content/hero.uasset"), EntryFetchOptions::default())
+                .await
+                .unwrap();
+            file_info(&entry).2
+        };
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(new_directory(
+            "",
+            vec![new_directory_entry("content", vec![new_file("hero.uasset")])],
+        ));
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![new_file("hero.uasset")])]),
+        });
+        assert_eq!(conflict_state(&mock_api).await, ConflictState::None);
+
+        // Simulate another user submitting a new version of the hero
+        let theirs_metadata = FileMetadata::new(100, 2000);
+        let mut theirs = new_file("hero.uasset");
+        if let DirectoryEntryType::File { metadata, .. } = theirs.info_mut() {
+            *metadata = theirs_metadata.clone();
+        }
+        mock_api.add_snapshot(MockSnapshot {
+            name: "theirs".to_string(),
+            revision: Revision::new(2),
+            submitted_time_unix_ms_utc: 2000,
+            tree: new_directory("", vec![new_directory_entry("content", vec![theirs])]),
+        });
+        assert_eq!(conflict_state(&mock_api).await, ConflictState::Incoming);
+
+        mock_api.mark_for_edit(&hero_path).await.unwrap();
+        let result = mock_api
+            .submit(SubmitTarget::Paths(vec![hero_path.clone()]), "Out of date")
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::OutOfDate(_))));
+        mock_api
+            .revert(std::slice::from_ref(&hero_path), RevertOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            conflict_state(&mock_api).await,
+            ConflictState::Incoming,
+            "Reverting should keep incoming changes"
+        );
+
+        let progress = mock_api
+            .sync(&RelativePath::default(), None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(progress.len(), 1);
+        assert_eq!(
+            conflict_state(&mock_api).await,
+            ConflictState::None,
+            "Syncing should clear incoming changes"
+        );
+
+        let ours_metadata = FileMetadata::new(150, 3000);
+        mock_api.set_local_file_metadata(hero_path.clone(), ours_metadata.clone());
+        mock_api.mark_for_edit(&hero_path).await.unwrap();
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![hero_path.clone()]), "Hero pose")
+            .await
+            .unwrap();
+        assert_eq!(revision, Revision::new(3));
+        let changes = mock_api.preview_sync(&RelativePath::default(), None).await.unwrap();
+        assert!(
+            changes.is_empty(),
+            "Submitted files should not come back as incoming changes"
+        );
+        let root = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                DirectoryFetchOptions {
+                    revision: Some(RevisionSelector::Revision(revision)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let entry = find_entry(&root, &hero_path).unwrap();
+        assert_eq!(file_info(entry).0, ours_metadata);
+    }
+
+    #[tokio::test]
+    async fn test_workspaces() {
+        let root_files = async |mock_api: &MockWorkspaceApi| {
+            let root = mock_api
+                .fetch_directory(&RelativePath::default(), DirectoryFetchOptions::default())
+                .await
+                .unwrap();
+            root.files().map(|(path, _)| path.to_string()).collect::<Vec<_>>()
+        };
+        let tree = new_directory(
+            "",
+            vec![
+                new_directory_entry(
+                    "Content",
+                    vec![
+                        new_file("Hero.uasset"),
+                        new_directory_entry("Movies", vec![new_file("Intro.mp4"), new_file("Trailer.mp4")]),
+                    ],
+                ),
+                new_directory_entry("Source", vec![new_file("Main.cpp")]),
+            ],
+        );
+        let mut mock_api = MockWorkspaceApi::with_directory_tree(tree.clone());
+        mock_api.add_snapshot(MockSnapshot {
+            name: "initial".to_string(),
+            revision: Revision::new(1),
+            submitted_time_unix_ms_utc: 1000,
+            tree,
+        });
+
+        let mut current = mock_api.current_workspace().await.unwrap();
+        assert_eq!(current.name, DEFAULT_WORKSPACE_NAME);
+        assert_eq!(current.view, WorkspaceSpec::full_view());
+        assert_eq!(root_files(&mock_api).await.len(), 4);
+
+        let sparse = WorkspaceSpec {
+            name: "sparse".to_string(),
+            owner: "alice".to_string(),
+            root: PathBuf::from("/work/sparse"),
+            view: vec![mapping(ViewMappingKind::Include, "Source", "")],
+        };
+        mock_api.create_workspace(sparse.clone()).await.unwrap();
+        let result = mock_api.create_workspace(sparse.clone()).await;
+        assert!(matches!(result, Err(WorkspaceApiError::WorkspaceAlreadyExists(_))));
+        let names = mock_api
+            .list_workspaces()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|spec| spec.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![DEFAULT_WORKSPACE_NAME, "sparse"]);
+        assert_eq!(
+            root_files(&mock_api).await.len(),
+            4,
+            "Other workspaces should not affect the current workspace"
+        );
+
+        current
+            .view
+            .push(mapping(ViewMappingKind::Exclude, "Content/Movies", ""));
+        mock_api.update_workspace(current.clone()).await.unwrap();
+        assert_eq!(
+            root_files(&mock_api).await,
+            vec!["Content/Hero.uasset", "Source/Main.cpp"]
+        );
+        let result = mock_api
+            .fetch_directory(&path("Content/Movies"), DirectoryFetchOptions::default())
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        let root = mock_api
+            .fetch_directory(
+                &RelativePath::default(),
+                DirectoryFetchOptions {
+                    revision: Some(RevisionSelector::Revision(Revision::new(1))),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            root.files().map(|(path, _)| path.to_string()).collect::<Vec<_>>(),
+            vec!["Content/Hero.uasset", "Source/Main.cpp"],
+            "Fetches at a revision should also apply the view"
+        );
+
+        // Mappings can also move depot paths within the workspace
+        current.view = vec![
+            mapping(ViewMappingKind::Include, "Content", "Art"),
+            mapping(ViewMappingKind::Exclude, "Content/Movies/Trailer.mp4", ""),
+        ];
+        mock_api.update_workspace(current).await.unwrap();
+        assert_eq!(
+            root_files(&mock_api).await,
+            vec!["Art/Hero.uasset", "Art/Movies/Intro.mp4"]
+        );
+        let entry = mock_api
+            .fetch_entry(&path("Art/Movies"), EntryFetchOptions::default())
+            .await
+            .unwrap();
+        assert!(matches!(entry.info(), DirectoryEntryType::Directory(None)));
+
+        // Every other operation addresses files by their workspace paths too
+        let watch = mock_api.watch(&path("Art"), true);
+        let entry = mock_api.mark_for_edit(&path("Art/Hero.uasset")).await.unwrap();
+        assert_eq!(entry.name(), "Hero.uasset");
+        assert!(matches!(
+            entry.info(),
+            DirectoryEntryType::File {
+                change_state: ChangeState::Modified,
+                ..
+            }
+        ));
+        for unmapped_path in ["Content/Hero.uasset", "Art/Movies/Trailer.mp4", "Source/Main.cpp"] {
+            let result = mock_api.mark_for_edit(&path(unmapped_path)).await;
+            assert!(
+                matches!(result, Err(WorkspaceApiError::NotFound(ref not_found)) if *not_found == path(unmapped_path)),
+                "Files outside the view should not be found"
+            );
+            let result = mock_api.lock(&path(unmapped_path)).await;
+            assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+        }
+        let changelists = mock_api.list_pending_changes(&path("Art")).await.unwrap();
+        let pending_paths = changelists[0]
+            .changes
+            .iter()
+            .map(|change| change.path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(pending_paths, vec!["Art/Hero.uasset"]);
+        let result = mock_api.list_pending_changes(&path("Content")).await;
+        assert!(matches!(result, Err(WorkspaceApiError::NotFound(_))));
+
+        mock_api.lock(&path("Art/Movies/Intro.mp4")).await.unwrap();
+        assert_eq!(
+            mock_api.list_locks(&path("Art/Movies")).await.unwrap(),
+            vec![FileLock {
+                path: path("Art/Movies/Intro.mp4"),
+                lock: Lock::LockedByYou,
+            }]
+        );
+        mock_api.mark_for_delete(&path("Art/Movies/Intro.mp4")).await.unwrap();
+        let reverted = mock_api
+            .revert(&[path("Art/Movies")], RevertOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(reverted.len(), 1);
+        assert_eq!(reverted[0].path, path("Art/Movies/Intro.mp4"));
+
+        let revision = mock_api
+            .submit(SubmitTarget::Paths(vec![path("Art")]), "Hero tweaks")
+            .await
+            .unwrap();
+        let changeset = mock_api.fetch_changeset(revision).await.unwrap();
+        assert_eq!(changeset.changes[0].path, path("Art/Hero.uasset"));
+        let history = mock_api
+            .file_history(&path("Art/Hero.uasset"), HistoryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(history[0].path, path("Art/Hero.uasset"));
+        let events = watch
+            .take(4)
+            .map(|event| event.unwrap().path.to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                "Art/Hero.uasset",
+                "Art/Movies/Intro.mp4",
+                "Art/Movies/Intro.mp4",
+                "Art/Movies/Intro.mp4"
+            ],
+            "Watch events should carry workspace paths"
+        );
+
+        let result = mock_api
+            .update_workspace(WorkspaceSpec {
+                name: "missing".to_string(),
+                ..sparse
+            })
+            .await;
+        assert!(matches!(result, Err(WorkspaceApiError::WorkspaceNotFound(_))));
+        let result = mock_api.delete_workspace(DEFAULT_WORKSPACE_NAME).await;
+        assert!(matches!(result, Err(WorkspaceApiError::WorkspaceInUse(_))));
+        mock_api.delete_workspace("sparse").await.unwrap();
+        let result = mock_api.delete_workspace("sparse").await;
+        assert!(matches!(result, Err(WorkspaceApiError::WorkspaceNotFound(_))));
+    }
+
+    fn path(path: &str) -> RelativePath {
+        RelativePath::new(path).unwrap()
+    }
+
+    fn at(revision: RevisionSelector) -> DirectoryFetchOptions {
+        DirectoryFetchOptions {
+            revision: Some(revision),
+            ..Default::default()
+        }
+    }
+
+    fn mapping(kind: ViewMappingKind, depot_path: &str, workspace_path: &str) -> ViewMapping {
+        ViewMapping {
+            kind,
+            depot_path: path(depot_path),
+            workspace_path: path(workspace_path),
+        }
+    }
+
+    fn file_info(entry: &DirectoryEntry) -> (FileMetadata, ChangeState, ConflictState) {
+        match entry.info() {
+            DirectoryEntryType::File {
+                metadata,
+                change_state,
+                conflict_state,
+                ..
+            } => (metadata.clone(), *change_state, *conflict_state),
+            DirectoryEntryType::Directory(_) => panic!("Entry '{}' should be a file", entry.name()),
+        }
+    }
+
+    fn lock_info(entry: &DirectoryEntry) -> Lock {
+        match entry.info() {
+            DirectoryEntryType::File { lock, .. } => lock.clone(),
+            DirectoryEntryType::Directory(_) => panic!("Entry '{}' should be a file", entry.name()),
+        }
+    }
+
+    fn file_paths(directory: &Directory) -> Vec<String> {
+        directory.files().map(|(path, _)| path.to_string()).collect()
+    }
+
+    fn file_infos(directory: &Directory) -> Vec<(String, (FileMetadata, ChangeState, ConflictState))> {
+        directory
+            .files()
+            .map(|(path, entry)| (path.to_string(), file_info(entry)))
+            .collect()
+    }
+
+    fn diff_changes(diff: &Directory) -> Vec<(String, ChangeState)> {
+        diff.files()
+            .map(|(path, entry)| (path.to_string(), file_info(entry).1))
+            .collect()
+    }
+
+    fn change_summary(changes: &[PendingChange]) -> Vec<(String, ChangeState, FileMetadata)> {
+        changes
+            .iter()
+            .map(|change| (change.path.to_string(), change.change_state, change.metadata.clone()))
+            .collect()
+    }
+
+    fn actions(changes: &[IncomingChange]) -> Vec<(String, SyncAction)> {
+        changes
+            .iter()
+            .map(|change| (change.path.to_string(), change.action))
+            .collect()
+    }
+
+    fn revision_numbers(revisions: &[FileRevision]) -> Vec<u64> {
+        revisions.iter().map(|revision| revision.revision.number()).collect()
+    }
+
+    fn changeset_ids(changesets: &[Changeset]) -> Vec<u64> {
+        changesets.iter().map(|changeset| changeset.id.number()).collect()
+    }
+
+    fn stream_names(streams: Vec<Stream>) -> Vec<String> {
+        streams.into_iter().map(|stream| stream.name).collect()
+    }
+
+    fn changelist_paths(changelists: &[Changelist]) -> Vec<(&str, Vec<&str>)> {
+        changelists
+            .iter()
+            .map(|changelist| {
+                let paths = changelist.changes.iter().map(|change| change.path.as_str()).collect();
+                (changelist.name.as_str(), paths)
+            })
+            .collect()
+    }
+
+    async fn fetch_names(mock_api: &MockWorkspaceApi, path: &str, options: DirectoryFetchOptions) -> Vec<String> {
+        let directory = mock_api
+            .fetch_directory(&RelativePath::new(path).unwrap(), options)
+            .await
+            .expect("Fetch should succeed");
+
+        let mut names = vec![];
+        collect_names(&directory, &mut names);
+        names
+    }
+
+    fn collect_names(dir: &Directory, names: &mut Vec<String>) {
+        for entry in dir.entries() {
+            let mut full_path_string = dir.relative_path().try_join(entry.name()).unwrap().to_string();
+            if matches!(entry.info(), DirectoryEntryType::Directory(None)) {
+                full_path_string.push_str(" (unloaded)");
+            }
+
+            names.push(full_path_string);
+            if let DirectoryEntryType::Directory(Some(sub_dir)) = entry.info() {
+                collect_names(sub_dir, names);
+            }
+        }
+    }
+
+    fn count_files(dir: &Directory) -> u64 {
+        dir.entries()
+            .iter()
+            .map(|entry| match entry.info() {
+                DirectoryEntryType::File { .. } => 1,
+                DirectoryEntryType::Directory(Some(sub_dir)) => count_files(sub_dir),
+                DirectoryEntryType::Directory(None) => 0,
+            })
+            .sum()
+    }
+
+    /// Builds a directory at the given path, fixing up the relative paths of any nested directories
+    fn new_directory(path: &str, entries: Vec<DirectoryEntry>) -> Directory {
+        let relative_path = RelativePath::new(path).unwrap();
+        let entries = entries
+            .into_iter()
+            .map(|entry| match entry.info() {
+                DirectoryEntryType::Directory(Some(dir)) => {
+                    let sub_path = relative_path.try_join(entry.name()).unwrap();
+                    let sub_dir = new_directory(sub_path.as_str(), dir.entries().to_vec());
+                    DirectoryEntry::new(entry.name().to_string(), DirectoryEntryType::Directory(Some(sub_dir)))
+                }
+                _ => entry,
+            })
+            .collect();
+        Directory::new(relative_path, entries)
+    }
+
+    fn new_directory_entry(name: &str, entries: Vec<DirectoryEntry>) -> DirectoryEntry {
+        DirectoryEntry::new(
+            name.to_string(),
+            DirectoryEntryType::Directory(Some(new_directory(name, entries))),
+        )
+    }
+
+    fn new_file(name: &str) -> DirectoryEntry {
+        new_file_with_states(name, Default::default(), Default::default())
+    }
+
+    fn new_sized_file(name: &str, size_bytes: u64, modified_time_unix_ms_utc: u64) -> DirectoryEntry {
+        DirectoryEntry::new(
+            name.to_string(),
+            DirectoryEntryType::File {
+                metadata: FileMetadata::new(size_bytes, modified_time_unix_ms_utc),
+                change_state: ChangeState::Unchanged,
+                conflict_state: ConflictState::None,
+                lock: Lock::Unlocked,
+            },
+        )
+    }
+
+    fn new_conflict(file_path: &str, kind: ConflictKind, theirs_metadata: Option<FileMetadata>) -> ConflictInfo {
+        ConflictInfo {
+            path: path(file_path),
+            kind,
+            conflict_state: ConflictState::Unresolved,
+            base_revision: Revision::new(3),
+            theirs_revision: Revision::new(5),
+            yours_revision: Revision::new(3),
+            published_by: "alice".to_string(),
+            published_time_unix_ms_utc: 1_700_000_000_000,
+            theirs_metadata,
+        }
+    }
+
+    fn new_file_with_states(name: &str, change_state: ChangeState, conflict_state: ConflictState) -> DirectoryEntry {
+        DirectoryEntry::new(
+            name.to_string(),
+            DirectoryEntryType::File {
+                metadata: FileMetadata::new(0, 0),
+                change_state,
+                conflict_state,
+                lock: Lock::Unlocked,
+            },
+        )
     }
 }
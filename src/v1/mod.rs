@@ -2,3 +2,5 @@ pub mod client;
 #[cfg(feature = "mock_client")]
 pub mod mock_client;
 pub mod model;
+
+pub use client::WorkspaceApiError;
@@ -1,7 +1,8 @@
 // == Std
+use std::{collections::BTreeMap, fmt::Display, fmt::Write, num::NonZeroU32, path::PathBuf};
 
 // == Internal crates
-use crate::common::RelativePath;
+use crate::common::{RelativePath, RelativePathComponents};
 
 // == External crates
 use enumset::{EnumSet, EnumSetType};
@@ -10,10 +11,15 @@ use serde::{Deserialize, Serialize};
 
 pub type ChangeStateSet = EnumSet<ChangeState>;
 pub type ConflictStateSet = EnumSet<ConflictState>;
+pub type ChangeStateCounts = StateCounts<ChangeState>;
+pub type ConflictStateCounts = StateCounts<ConflictState>;
+pub type LockStateSet = EnumSet<LockState>;
+pub type LockStateCounts = StateCounts<LockState>;
 
 /// Represents a directory in the workspace, containing its relative path and entries.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedDirectory"))]
 pub struct Directory {
     /// The full relative path of this directory within the workspace
     relative_path: RelativePath,
@@ -23,25 +29,72 @@ pub struct Directory {
     conflict_states: ConflictStateSet,
     /// The aggregated union of change states of all entries within this directory
     change_states: ChangeStateSet,
+    /// The number of files within this directory (recursively) in each conflict state
+    conflict_state_counts: ConflictStateCounts,
+    /// The number of files within this directory (recursively) in each change state
+    change_state_counts: ChangeStateCounts,
+    /// The aggregated union of lock states of all entries within this directory
+    lock_states: LockStateSet,
+    /// The number of files within this directory (recursively) in each lock state
+    lock_state_counts: LockStateCounts,
+}
+
+/// Deserialization helper for Directory, which allows for data serialized before per-state counts were added
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedDirectory {
+    relative_path: RelativePath,
+    entries: Vec<DirectoryEntry>,
+    #[serde(default)]
+    conflict_states: ConflictStateSet,
+    #[serde(default)]
+    change_states: ChangeStateSet,
+    conflict_state_counts: Option<ConflictStateCounts>,
+    change_state_counts: Option<ChangeStateCounts>,
+    #[serde(default)]
+    lock_states: LockStateSet,
+    lock_state_counts: Option<LockStateCounts>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedDirectory> for Directory {
+    fn from(serialized: SerializedDirectory) -> Self {
+        match (
+            serialized.conflict_state_counts,
+            serialized.change_state_counts,
+            serialized.lock_state_counts,
+        ) {
+            (Some(conflict_state_counts), Some(change_state_counts), Some(lock_state_counts)) => Directory {
+                relative_path: serialized.relative_path,
+                entries: serialized.entries,
+                conflict_states: serialized.conflict_states,
+                change_states: serialized.change_states,
+                conflict_state_counts,
+                change_state_counts,
+                lock_states: serialized.lock_states,
+                lock_state_counts,
+            },
+            // Without counts the aggregates can only be recomputed from the entries, which assumes they are loaded
+            _ => Directory::new(serialized.relative_path, serialized.entries),
+        }
+    }
 }
 
 impl Directory {
     /// Creates a new Directory with the given relative path and entries
     pub fn new(relative_path: RelativePath, entries: Vec<DirectoryEntry>) -> Self {
-        // Aggregate the child conflict states and change states
-        let (conflict_states, change_states) = entries.iter().fold(
-            (ConflictStateSet::default(), ChangeStateSet::default()),
-            |(mut conflicts, mut changes), entry| {
-                entry.aggregate_states_into(&mut conflicts, &mut changes);
-                (conflicts, changes)
-            },
-        );
-        Directory {
+        let mut directory = Directory {
             relative_path,
             entries,
-            conflict_states,
-            change_states,
-        }
+            conflict_states: ConflictStateSet::default(),
+            change_states: ChangeStateSet::default(),
+            conflict_state_counts: ConflictStateCounts::default(),
+            change_state_counts: ChangeStateCounts::default(),
+            lock_states: LockStateSet::default(),
+            lock_state_counts: LockStateCounts::default(),
+        };
+        directory.recompute_states();
+        directory
     }
 
     /// Returns the relative path of this directory
@@ -54,12 +107,145 @@ impl Directory {
         &self.entries
     }
 
+    /// Returns the aggregated union of conflict states of all files within this directory
+    pub fn conflict_states(&self) -> ConflictStateSet {
+        self.conflict_states
+    }
+
+    /// Returns the aggregated union of change states of all files within this directory
+    pub fn change_states(&self) -> ChangeStateSet {
+        self.change_states
+    }
+
+    /// Returns the number of files within this directory (recursively) in each conflict state
+    pub fn conflict_state_counts(&self) -> &ConflictStateCounts {
+        &self.conflict_state_counts
+    }
+
+    /// Returns the number of files within this directory (recursively) in each change state
+    pub fn change_state_counts(&self) -> &ChangeStateCounts {
+        &self.change_state_counts
+    }
+
+    /// Returns the aggregated union of lock states of all files within this directory
+    /// For example, `LockState::LockedByOther` is included if any file below this directory is locked by another user
+    pub fn lock_states(&self) -> LockStateSet {
+        self.lock_states
+    }
+
+    /// Returns the number of files within this directory (recursively) in each lock state
+    pub fn lock_state_counts(&self) -> &LockStateCounts {
+        &self.lock_state_counts
+    }
+
     pub fn push_entry(&mut self, entry: DirectoryEntry) {
         // TODO: Make sure these stay sorted and unique
-        entry.aggregate_states_into(&mut self.conflict_states, &mut self.change_states);
+        self.aggregate_entry_states(&entry);
         self.entries.push(entry);
     }
 
+    /// Recursively removes entries for which `predicate` returns false.
+    /// Directories matching the predicate are kept with all of their contents, other directories are only kept if any
+    /// of their descendants are kept.  Unloaded directories have no descendants to test, so are only kept if they
+    /// match.
+    /// The aggregated states of this directory and any filtered sub-directories are recomputed over the kept entries.
+    pub fn retain_recursive(&mut self, predicate: &mut impl FnMut(&DirectoryEntry) -> bool) {
+        self.entries.retain_mut(|entry| {
+            if predicate(entry) {
+                return true;
+            }
+
+            match &mut entry.info {
+                DirectoryEntryType::Directory(Some(dir)) => {
+                    dir.retain_recursive(predicate);
+                    !dir.entries.is_empty()
+                }
+                _ => false,
+            }
+        });
+        self.recompute_states();
+    }
+
+    /// Recursively removes files whose states are not contained in the given filters, along with any directories left
+    /// without matching files.  A filter of `None` matches every state, and files must match both filters to be kept.
+    /// Sub-directories whose aggregated states do not intersect the filters are dropped without being visited, and
+    /// unloaded directories are always dropped, since their states are unknown.
+    pub fn retain_states(
+        &mut self,
+        change_state_filter: Option<ChangeStateSet>,
+        conflict_state_filter: Option<ConflictStateSet>,
+    ) {
+        let change_state_filter = change_state_filter.unwrap_or(ChangeStateSet::all());
+        let conflict_state_filter = conflict_state_filter.unwrap_or(ConflictStateSet::all());
+
+        self.entries.retain_mut(|entry| match &mut entry.info {
+            DirectoryEntryType::File {
+                change_state,
+                conflict_state,
+                ..
+            } => change_state_filter.contains(*change_state) && conflict_state_filter.contains(*conflict_state),
+            DirectoryEntryType::Directory(Some(dir)) => {
+                if dir.change_states.is_disjoint(change_state_filter)
+                    || dir.conflict_states.is_disjoint(conflict_state_filter)
+                {
+                    return false;
+                }
+
+                dir.retain_states(Some(change_state_filter), Some(conflict_state_filter));
+                !dir.entries.is_empty()
+            }
+            DirectoryEntryType::Directory(None) => false,
+        });
+        self.recompute_states();
+    }
+
+    /// Recomputes the aggregated states of this directory from its immediate entries
+    /// Note that unloaded sub-directories contribute nothing to the aggregate
+    fn recompute_states(&mut self) {
+        self.conflict_states = ConflictStateSet::default();
+        self.change_states = ChangeStateSet::default();
+        self.conflict_state_counts = ConflictStateCounts::default();
+        self.change_state_counts = ChangeStateCounts::default();
+        self.lock_states = LockStateSet::default();
+        self.lock_state_counts = LockStateCounts::default();
+
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.aggregate_entry_states(entry);
+        }
+        self.entries = entries;
+    }
+
+    /// Adds the states of the given entry to the aggregated states of this directory
+    fn aggregate_entry_states(&mut self, entry: &DirectoryEntry) {
+        match &entry.info {
+            DirectoryEntryType::File {
+                conflict_state,
+                change_state,
+                lock,
+                ..
+            } => {
+                self.conflict_states.insert(*conflict_state);
+                self.change_states.insert(*change_state);
+                self.lock_states.insert(lock.state());
+                self.conflict_state_counts.increment(*conflict_state);
+                self.change_state_counts.increment(*change_state);
+                self.lock_state_counts.increment(lock.state());
+            }
+            DirectoryEntryType::Directory(Some(dir)) => {
+                self.conflict_states.insert_all(dir.conflict_states);
+                self.change_states.insert_all(dir.change_states);
+                self.lock_states.insert_all(dir.lock_states);
+                self.conflict_state_counts.add_all(&dir.conflict_state_counts);
+                self.change_state_counts.add_all(&dir.change_state_counts);
+                self.lock_state_counts.add_all(&dir.lock_state_counts);
+            }
+            DirectoryEntryType::Directory(None) => {
+                // Unloaded directory, do nothing
+            }
+        }
+    }
+
     /// Prunes (unloads, i.e. sets to None) directory sub-entries beyond the specified depth limit
     pub fn prune_to_depth(&mut self, depth_limit: u32) {
         for entry in &mut self.entries {
@@ -73,6 +259,598 @@ impl Directory {
             }
         }
     }
+
+    /// Unloads all loaded sub-directories of this directory, returning them in entry order.
+    /// Aggregated states are not recomputed, so they continue to describe the unloaded contents.
+    pub fn unload_subdirectories(&mut self) -> Vec<Directory> {
+        self.entries
+            .iter_mut()
+            .filter_map(|entry| match &mut entry.info {
+                DirectoryEntryType::Directory(dir) => dir.take(),
+                DirectoryEntryType::File { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Inserts a loaded directory at its relative path below this directory, replacing the existing (typically
+    /// unloaded) directory entry at that path.  This can be used to assemble a tree from directories fetched
+    /// separately, for example via `WorkspaceApi::fetch_directory_stream`.
+    /// Aggregated states are not recomputed, as they are expected to already describe the unloaded contents.
+    /// Returns the directory back as an error if there is no directory entry at its path below this directory.
+    pub fn insert_loaded_directory(&mut self, directory: Directory) -> Result<(), Box<Directory>> {
+        let Some(remaining_components) = self.components_below(&directory.relative_path) else {
+            return Err(Box::new(directory));
+        };
+        let remaining_components = remaining_components.map(str::to_string).collect::<Vec<_>>();
+        let Some((name, parent_components)) = remaining_components.split_last() else {
+            // The directory has the same path as this one
+            return Err(Box::new(directory));
+        };
+
+        let mut current = self;
+        for component in parent_components {
+            match current.entries.iter_mut().find(|entry| &entry.name == component) {
+                Some(DirectoryEntry {
+                    info: DirectoryEntryType::Directory(Some(dir)),
+                    ..
+                }) => current = dir,
+                _ => return Err(Box::new(directory)),
+            }
+        }
+
+        match current.entries.iter_mut().find(|entry| &entry.name == name) {
+            Some(entry) if matches!(entry.info, DirectoryEntryType::Directory(_)) => {
+                entry.info = DirectoryEntryType::Directory(Some(directory));
+                Ok(())
+            }
+            _ => Err(Box::new(directory)),
+        }
+    }
+
+    /// Finds the loaded directory at the given relative path, which may be this directory or any loaded directory
+    /// below it.  Returns None if the path is not below this directory, does not exist, or is not loaded.
+    pub fn find_directory(&self, path: &RelativePath) -> Option<&Directory> {
+        let mut current = self;
+        for component in self.components_below(path)? {
+            match current.entries.iter().find(|entry| entry.name == component)?.info() {
+                DirectoryEntryType::Directory(Some(dir)) => current = dir,
+                _ => return None,
+            }
+        }
+        Some(current)
+    }
+
+    /// Returns an iterator over every file in the loaded tree below this directory, depth first in entry order, along
+    /// with the full relative path of each file.  Unloaded directories are skipped.
+    pub fn files(&self) -> impl Iterator<Item = (RelativePath, &DirectoryEntry)> {
+        let mut stack = vec![(self, self.entries.iter())];
+        std::iter::from_fn(move || {
+            while let Some((dir, entries)) = stack.last_mut() {
+                let dir = *dir;
+                match entries.next() {
+                    Some(entry) => match &entry.info {
+                        DirectoryEntryType::File { .. } => {
+                            let path = dir
+                                .relative_path
+                                .try_join(&entry.name)
+                                .expect("Entry names should be valid relative paths");
+                            return Some((path, entry));
+                        }
+                        DirectoryEntryType::Directory(Some(sub_dir)) => stack.push((sub_dir, sub_dir.entries.iter())),
+                        DirectoryEntryType::Directory(None) => {}
+                    },
+                    None => {
+                        stack.pop();
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns the components of the given path below this directory, or None if the path is not below it
+    fn components_below<'a>(&self, path: &'a RelativePath) -> Option<RelativePathComponents<'a>> {
+        let mut path_components = path.components();
+        for component in self.relative_path.components() {
+            if path_components.next() != Some(component) {
+                return None;
+            }
+        }
+        Some(path_components)
+    }
+
+    /// Calls `f` with the loaded directory at the given path, which may be this directory or any loaded directory
+    /// below it, then recomputes the aggregated states of that directory and all of its ancestors up to this one.
+    /// Returns None, without calling `f`, if there is no loaded directory at the path.
+    pub fn update_directory<R>(&mut self, path: &RelativePath, f: impl FnOnce(&mut Directory) -> R) -> Option<R> {
+        let components = self.components_below(path)?.collect::<Vec<_>>();
+        self.update_directory_at(&components, f)
+    }
+
+    fn update_directory_at<R>(&mut self, components: &[&str], f: impl FnOnce(&mut Directory) -> R) -> Option<R> {
+        let result = match components.split_first() {
+            None => f(self),
+            Some((name, remaining_components)) => match &mut self.entry_mut(name)?.info {
+                DirectoryEntryType::Directory(Some(dir)) => dir.update_directory_at(remaining_components, f)?,
+                _ => return None,
+            },
+        };
+        self.recompute_states();
+        Some(result)
+    }
+
+    /// Returns the immediate entry with the given name
+    pub fn entry(&self, name: &str) -> Option<&DirectoryEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns the immediate entry with the given name, mutably
+    /// Aggregated states are not recomputed, see `update_directory`
+    pub fn entry_mut(&mut self, name: &str) -> Option<&mut DirectoryEntry> {
+        self.entries.iter_mut().find(|entry| entry.name == name)
+    }
+
+    /// Inserts an entry in name order, assuming the existing entries are already sorted by name
+    /// Aggregated states are not recomputed, see `update_directory`.
+    /// Returns the replaced entry if an entry with the same name already existed
+    pub fn insert_entry(&mut self, entry: DirectoryEntry) -> Option<DirectoryEntry> {
+        match self.entries.binary_search_by(|existing| existing.name.cmp(&entry.name)) {
+            Ok(index) => Some(std::mem::replace(&mut self.entries[index], entry)),
+            Err(index) => {
+                self.entries.insert(index, entry);
+                None
+            }
+        }
+    }
+
+    /// Removes and returns the immediate entry with the given name
+    /// Aggregated states are not recomputed, see `update_directory`
+    pub fn remove_entry(&mut self, name: &str) -> Option<DirectoryEntry> {
+        let index = self.entries.iter().position(|entry| entry.name == name)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Sorts the entries by name, then retains at most `page_size` entries following the entry named `start_after`, or
+    /// from the first entry if `start_after` is `None`.  Aggregated states are not recomputed, so they continue to
+    /// describe the full directory.
+    /// Returns the name of the last retained entry if further entries follow it
+    pub fn retain_page(&mut self, start_after: Option<&str>, page_size: Option<NonZeroU32>) -> Option<String> {
+        // Sibling names compare the same way as their full RelativePaths, since the parent components are equal
+        self.entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let start = start_after.map_or(0, |name| {
+            self.entries.partition_point(|entry| entry.name.as_str() <= name)
+        });
+        self.entries.drain(..start);
+
+        let page_size = page_size.map_or(usize::MAX, |size| size.get() as usize);
+        if self.entries.len() > page_size {
+            self.entries.truncate(page_size);
+            self.entries.last().map(|entry| entry.name.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// Diffs two directory trees, returning a directory containing only the files which differ between them.
+/// The change state of each file describes how it changed from `from` to `to`: files only in `to` are added, files only
+/// in `from` are deleted, and files whose metadata differs are modified, each with the metadata of its newest version.
+/// Directories are only included if they contain a difference.  Each name appears once, so an entry which changes
+/// between a file and a directory is included as it is in `to`: a file replacing a directory is modified, and a
+/// directory replacing a file contains its files as added.  The files of a replaced directory are not listed.
+/// All conflict states are `ConflictState::None`.  Unloaded directories are skipped, so both trees should be fully
+/// loaded.  The result has the relative path of `to`.
+pub fn diff_directories(from: &Directory, to: &Directory) -> Directory {
+    diff_directory_entries(to.relative_path.clone(), Some(from), Some(to))
+}
+
+/// Diffs the entries of two optional directories, see `diff_directories`
+fn diff_directory_entries(relative_path: RelativePath, from: Option<&Directory>, to: Option<&Directory>) -> Directory {
+    let mut entry_pairs = BTreeMap::<&str, (Option<&DirectoryEntryType>, Option<&DirectoryEntryType>)>::new();
+    for entry in from.into_iter().flat_map(|directory| &directory.entries) {
+        entry_pairs.entry(&entry.name).or_default().0 = Some(&entry.info);
+    }
+    for entry in to.into_iter().flat_map(|directory| &directory.entries) {
+        entry_pairs.entry(&entry.name).or_default().1 = Some(&entry.info);
+    }
+
+    let diff_file = |metadata: &FileMetadata, change_state| DirectoryEntryType::File {
+        metadata: metadata.clone(),
+        change_state,
+        conflict_state: ConflictState::None,
+        lock: Lock::Unlocked,
+    };
+    let mut entries = vec![];
+    for (name, (from_info, to_info)) in entry_pairs {
+        match (file_metadata(from_info), file_metadata(to_info)) {
+            (Some(from_metadata), Some(metadata)) => {
+                if from_metadata != metadata {
+                    entries.push(DirectoryEntry::new(
+                        name.to_string(),
+                        diff_file(metadata, ChangeState::Modified),
+                    ));
+                }
+            }
+            _ if matches!(from_info, Some(DirectoryEntryType::Directory(None)))
+                || matches!(to_info, Some(DirectoryEntryType::Directory(None))) => {}
+            // At most one side is a file here, as files on both sides are compared above
+            (from_metadata, to_metadata) => {
+                let (from_directory, to_directory) = (loaded_directory(from_info), loaded_directory(to_info));
+                let info = match to_metadata {
+                    Some(metadata) if from_directory.is_some() => Some(diff_file(metadata, ChangeState::Modified)),
+                    Some(metadata) => Some(diff_file(metadata, ChangeState::Added)),
+                    None if from_directory.is_some() || to_directory.is_some() => {
+                        let path = relative_path
+                            .try_join(name)
+                            .expect("Entry names should be valid relative paths");
+                        let directory = diff_directory_entries(path, from_directory, to_directory);
+                        (!directory.entries.is_empty()).then_some(DirectoryEntryType::Directory(Some(directory)))
+                    }
+                    None => from_metadata.map(|metadata| diff_file(metadata, ChangeState::Deleted)),
+                };
+                entries.extend(info.map(|info| DirectoryEntry::new(name.to_string(), info)));
+            }
+        }
+    }
+
+    Directory::new(relative_path, entries)
+}
+
+/// The number of unchanged lines of context shown around each change in a FileDiff
+pub const DIFF_CONTEXT_LINES: usize = 3;
+
+/// The most line edits a FileDiff searches for the shortest edit script with, beyond which the changed region is
+/// shown as replaced as a whole, bounding the time and memory needed to diff very different files
+pub const MAX_DIFF_EDITS: usize = 1000;
+
+/// The line-by-line differences between two versions of a file, see `WorkspaceApi::diff_file`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileDiff {
+    /// The full relative path of the file within the workspace
+    pub path: RelativePath,
+    /// Set if the two versions of the file differ at all
+    pub differs: bool,
+    /// Set if either version of the file is binary, in which case there are no hunks
+    pub is_binary: bool,
+    /// Set if the file does not exist in the `from` version, i.e. it was added
+    pub from_missing: bool,
+    /// Set if the file does not exist in the `to` version, i.e. it was deleted
+    pub to_missing: bool,
+    /// The changed regions of the file with their surrounding context, empty if the versions are identical
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// A changed region of a file with its surrounding context, see FileDiff
+/// As in unified diffs, line numbers start at 1, and a range with no lines starts at the line before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DiffHunk {
+    /// The first line of the hunk in the `from` version
+    pub from_start: usize,
+    /// The number of lines of the hunk in the `from` version
+    pub from_count: usize,
+    /// The first line of the hunk in the `to` version
+    pub to_start: usize,
+    /// The number of lines of the hunk in the `to` version
+    pub to_count: usize,
+    /// The lines of the hunk, in order
+    pub lines: Vec<DiffLine>,
+}
+
+/// A single line within a DiffHunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    /// The text of the line, without its trailing newline, so a carriage return of a CRLF line terminator is kept
+    pub text: String,
+    /// Set if this is the last line of its version of the file, and has no trailing newline
+    pub missing_newline: bool,
+}
+
+/// How a DiffLine differs between the two versions of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DiffLineKind {
+    /// The line is unchanged, and shown as context
+    Context,
+    /// The line only exists in the `to` version
+    Added,
+    /// The line only exists in the `from` version
+    Removed,
+}
+
+impl FileDiff {
+    /// Formats the diff as unified diff text, or a single line noting that the files differ for binary files
+    /// Returns an empty string if the versions are identical.
+    pub fn to_unified(&self) -> String {
+        let from_label = if self.from_missing {
+            "/dev/null".to_string()
+        } else {
+            format!("a/{}", self.path)
+        };
+        let to_label = if self.to_missing {
+            "/dev/null".to_string()
+        } else {
+            format!("b/{}", self.path)
+        };
+
+        let mut unified = String::new();
+        if !self.differs {
+            return unified;
+        }
+        if self.is_binary {
+            let _ = writeln!(unified, "Binary files {} and {} differ", from_label, to_label);
+            return unified;
+        }
+
+        let _ = writeln!(unified, "--- {}", from_label);
+        let _ = writeln!(unified, "+++ {}", to_label);
+        for hunk in &self.hunks {
+            let _ = writeln!(
+                unified,
+                "@@ -{},{} +{},{} @@",
+                hunk.from_start, hunk.from_count, hunk.to_start, hunk.to_count
+            );
+            for line in &hunk.lines {
+                let prefix = match line.kind {
+                    DiffLineKind::Context => ' ',
+                    DiffLineKind::Added => '+',
+                    DiffLineKind::Removed => '-',
+                };
+                let _ = writeln!(unified, "{}{}", prefix, line.text);
+                if line.missing_newline {
+                    let _ = writeln!(unified, "\\ No newline at end of file");
+                }
+            }
+        }
+        unified
+    }
+}
+
+/// Diffs two versions of a file's content line by line, with the given number of lines of context around each change.
+/// Lines are compared along with their line terminators.  A version of `None` means the file does not exist in that
+/// version, and is diffed as empty.  Content containing a NUL byte or invalid UTF-8 is treated as binary, in which case
+/// the diff only notes whether the versions differ.  Versions needing more than `MAX_DIFF_EDITS` line edits are diffed
+/// as their changed region being replaced as a whole.
+pub fn diff_file_contents(
+    path: RelativePath,
+    from: Option<&[u8]>,
+    to: Option<&[u8]>,
+    context_lines: usize,
+) -> FileDiff {
+    fn as_text(content: Option<&[u8]>) -> Option<&str> {
+        match content {
+            None => Some(""),
+            Some(content) if content.contains(&0) => None,
+            Some(content) => std::str::from_utf8(content).ok(),
+        }
+    }
+
+    let (Some(from_text), Some(to_text)) = (as_text(from), as_text(to)) else {
+        return FileDiff {
+            path,
+            differs: from != to,
+            is_binary: true,
+            from_missing: from.is_none(),
+            to_missing: to.is_none(),
+            hunks: vec![],
+        };
+    };
+
+    let from_lines = from_text.split_inclusive('\n').collect::<Vec<_>>();
+    let to_lines = to_text.split_inclusive('\n').collect::<Vec<_>>();
+    let lines = diff_lines(&from_lines, &to_lines);
+    FileDiff {
+        path,
+        differs: from != to,
+        is_binary: false,
+        from_missing: from.is_none(),
+        to_missing: to.is_none(),
+        hunks: group_hunks(lines, context_lines),
+    }
+}
+
+/// Returns the shortest edit script between two lists of lines, or a replacement of the lines which differ if that
+/// needs more than `MAX_DIFF_EDITS` edits
+fn diff_lines<'a>(from: &[&'a str], to: &[&'a str]) -> Vec<(DiffLineKind, &'a str)> {
+    // Trim the common prefix and suffix, which are usually most of the file, before searching
+    let prefix = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+    let suffix = from[prefix..]
+        .iter()
+        .rev()
+        .zip(to[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let from_middle = &from[prefix..from.len() - suffix];
+    let to_middle = &to[prefix..to.len() - suffix];
+
+    let middle = shortest_edit_script(from_middle, to_middle).unwrap_or_else(|| {
+        let removed = from_middle.iter().map(|line| (DiffLineKind::Removed, *line));
+        let added = to_middle.iter().map(|line| (DiffLineKind::Added, *line));
+        removed.chain(added).collect()
+    });
+
+    let context = |lines: &[&'a str]| {
+        lines
+            .iter()
+            .map(|line| (DiffLineKind::Context, *line))
+            .collect::<Vec<_>>()
+    };
+    let mut lines = context(&from[..prefix]);
+    lines.extend(middle);
+    lines.extend(context(&from[from.len() - suffix..]));
+    lines
+}
+
+/// Returns the shortest edit script between two lists of lines using Myers' O(ND) algorithm, or `None` if it needs
+/// more than `MAX_DIFF_EDITS` edits.  Memory is bounded by the square of the number of edits.
+fn shortest_edit_script<'a>(from: &[&'a str], to: &[&'a str]) -> Option<Vec<(DiffLineKind, &'a str)>> {
+    let (n, m) = (from.len() as isize, to.len() as isize);
+    let max_edits = (n + m).min(MAX_DIFF_EDITS as isize);
+
+    // v[k] is the furthest x reached on diagonal k = x - y, offset so every diagonal within reach is indexable
+    let offset = max_edits + 1;
+    let index = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // The diagonals around those reachable with each number of edits, as they were before searching with that many
+    let mut trace = vec![];
+    let mut found = None;
+    'search: for d in 0..=max_edits {
+        trace.push(v[index(-d - 1)..=index(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && from[x as usize] == to[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                found = Some(d);
+                break 'search;
+            }
+        }
+    }
+    found?;
+
+    // Walk back from the end through the diagonals, recording each edit and the common lines between them
+    let mut script = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            script.push((DiffLineKind::Context, from[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                script.push((DiffLineKind::Added, to[y as usize - 1]));
+            } else {
+                script.push((DiffLineKind::Removed, from[x as usize - 1]));
+            }
+            (x, y) = (previous_x, previous_y);
+        }
+    }
+    script.reverse();
+    Some(script)
+}
+
+/// Groups an edit script into hunks, each change surrounded by up to `context_lines` lines of context
+fn group_hunks(lines: Vec<(DiffLineKind, &str)>, context_lines: usize) -> Vec<DiffHunk> {
+    // The number of lines of each version before each line of the edit script
+    let mut line_numbers = Vec::with_capacity(lines.len() + 1);
+    let (mut from_line, mut to_line) = (0, 0);
+    for (kind, _) in &lines {
+        line_numbers.push((from_line, to_line));
+        match kind {
+            DiffLineKind::Context => {
+                from_line += 1;
+                to_line += 1;
+            }
+            DiffLineKind::Added => to_line += 1,
+            DiffLineKind::Removed => from_line += 1,
+        }
+    }
+    line_numbers.push((from_line, to_line));
+
+    // Expand each change by the context, merging ranges which touch or overlap
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (kind, _))| *kind != DiffLineKind::Context)
+    {
+        let start = index.saturating_sub(context_lines);
+        let end = (index + context_lines + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let (from_before, to_before) = line_numbers[start];
+            let (from_after, to_after) = line_numbers[end];
+            let (from_count, to_count) = (from_after - from_before, to_after - to_before);
+            DiffHunk {
+                from_start: if from_count > 0 { from_before + 1 } else { from_before },
+                from_count,
+                to_start: if to_count > 0 { to_before + 1 } else { to_before },
+                to_count,
+                lines: lines[start..end]
+                    .iter()
+                    .map(|(kind, line)| DiffLine {
+                        kind: *kind,
+                        text: line.strip_suffix('\n').unwrap_or(line).to_string(),
+                        missing_newline: !line.ends_with('\n'),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the metadata if the entry type is a file
+fn file_metadata(info: Option<&DirectoryEntryType>) -> Option<&FileMetadata> {
+    match info {
+        Some(DirectoryEntryType::File { metadata, .. }) => Some(metadata),
+        _ => None,
+    }
+}
+
+/// Returns the directory if the entry type is a loaded directory
+fn loaded_directory(info: Option<&DirectoryEntryType>) -> Option<&Directory> {
+    match info {
+        Some(DirectoryEntryType::Directory(Some(directory))) => Some(directory),
+        _ => None,
+    }
+}
+
+/// A single page of a directory listing, see `WorkspaceApi::fetch_directory_page`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DirectoryPage {
+    /// The directory, containing only the entries within this page
+    /// The aggregated states still describe all of the directory's entries, not just those within this page
+    pub directory: Directory,
+    /// The cursor to fetch the following page with, or `None` if this is the last page
+    pub next_cursor: Option<PageCursor>,
+}
+
+/// An opaque cursor identifying the position to continue a paginated directory listing from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageCursor(String);
+
+impl PageCursor {
+    /// Creates a new PageCursor from its opaque string representation
+    pub fn new(cursor: impl Into<String>) -> Self {
+        PageCursor(cursor.into())
+    }
+
+    /// Returns the opaque string representation of the cursor
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 /// Represents an entry in a directory, which can be either a file or a sub-directory.
@@ -99,27 +877,20 @@ impl DirectoryEntry {
         &self.info
     }
 
-    pub(crate) fn aggregate_states_into(
-        &self,
-        conflict_states: &mut ConflictStateSet,
-        change_states: &mut ChangeStateSet,
-    ) {
+    /// Returns the type information of the directory entry, mutably
+    /// The aggregated states of any directories containing this entry are not recomputed, see
+    /// `Directory::update_directory`
+    pub fn info_mut(&mut self) -> &mut DirectoryEntryType {
+        &mut self.info
+    }
+
+    /// Returns a copy of this entry with any loaded directory contents unloaded
+    pub fn to_unloaded(&self) -> DirectoryEntry {
         match &self.info {
-            DirectoryEntryType::File {
-                conflict_state,
-                change_state,
-                ..
-            } => {
-                conflict_states.insert(*conflict_state);
-                change_states.insert(*change_state);
-            }
-            DirectoryEntryType::Directory(Some(dir)) => {
-                conflict_states.insert_all(dir.conflict_states);
-                change_states.insert_all(dir.change_states);
-            }
-            DirectoryEntryType::Directory(None) => {
-                // Unloaded directory, do nothing
+            DirectoryEntryType::Directory(Some(_)) => {
+                DirectoryEntry::new(self.name.clone(), DirectoryEntryType::Directory(None))
             }
+            _ => self.clone(),
         }
     }
 }
@@ -133,6 +904,9 @@ pub enum DirectoryEntryType {
         metadata: FileMetadata,
         change_state: ChangeState,
         conflict_state: ConflictState,
+        /// Whether the file is locked for exclusive checkout, and by whom, see `WorkspaceApi::lock`
+        #[cfg_attr(feature = "serde", serde(default))]
+        lock: Lock,
     },
     /// The entry is a directory.  If the inner value is None, the directory has not been loaded yet.
     Directory(Option<Directory>),
@@ -166,8 +940,425 @@ impl FileMetadata {
     }
 }
 
+/// Identifies a revision of the depot, revisions are numbered sequentially as changes are submitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Revision(u64);
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Revision {
+    /// Creates a new Revision with the given number
+    pub fn new(number: u64) -> Self {
+        Revision(number)
+    }
+
+    /// Returns the number of this revision
+    pub fn number(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A stream of the depot, i.e. a branch, which a workspace can be switched between, see `WorkspaceApi::list_streams`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stream {
+    /// The unique name of the stream
+    pub name: String,
+    /// The name of the stream this stream was branched from, or `None` for a mainline stream
+    pub parent: Option<String>,
+    /// The type of the stream
+    pub stream_type: StreamType,
+    /// The most recent revision submitted to the stream, or the revision it was branched at if nothing has been
+    /// submitted to it since
+    pub head_revision: Revision,
+}
+
+/// The type of a stream, see Stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StreamType {
+    /// The root of a stream hierarchy, with no parent
+    Mainline,
+    /// A stream for ongoing work, which is merged back to its parent
+    Development,
+    /// A stream for stabilizing a release, which only receives selected changes from its parent
+    Release,
+}
+
+/// The definition of a workspace, including which depot paths it maps and where, see `WorkspaceApi::list_workspaces`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WorkspaceSpec {
+    /// The unique name of the workspace
+    pub name: String,
+    /// The user who owns the workspace
+    pub owner: String,
+    /// The local directory the workspace is synced to
+    pub root: PathBuf,
+    /// The mappings between depot paths and workspace paths, in order, see `WorkspaceSpec::map_depot_path`
+    pub view: Vec<ViewMapping>,
+}
+
+impl WorkspaceSpec {
+    /// Returns a view mapping the whole depot to the same paths in the workspace, like an implicit workspace
+    pub fn full_view() -> Vec<ViewMapping> {
+        vec![ViewMapping {
+            kind: ViewMappingKind::Include,
+            depot_path: RelativePath::default(),
+            workspace_path: RelativePath::default(),
+        }]
+    }
+
+    /// Returns the workspace path the given depot path is mapped to, or `None` if it is not mapped.
+    /// As later mappings override earlier ones, the last mapping matching the depot path decides whether and where it
+    /// is mapped.
+    pub fn map_depot_path(&self, depot_path: &RelativePath) -> Option<RelativePath> {
+        self.view.iter().rev().find_map(|mapping| {
+            let remainder = depot_path.strip_prefix(&mapping.depot_path)?;
+            Some(match mapping.kind {
+                ViewMappingKind::Include => Some(mapping.workspace_path.join(&remainder)),
+                ViewMappingKind::Exclude => None,
+            })
+        })?
+    }
+
+    /// Returns the depot path mapped to the given workspace path, or `None` if no depot path is mapped to it.
+    /// This is the inverse of `map_depot_path`, so depot paths which a later mapping excludes or maps elsewhere are
+    /// skipped.
+    pub fn map_workspace_path(&self, workspace_path: &RelativePath) -> Option<RelativePath> {
+        self.view
+            .iter()
+            .rev()
+            .filter(|mapping| mapping.kind == ViewMappingKind::Include)
+            .filter_map(|mapping| {
+                let remainder = workspace_path.strip_prefix(&mapping.workspace_path)?;
+                Some(mapping.depot_path.join(&remainder))
+            })
+            .find(|depot_path| self.map_depot_path(depot_path).as_ref() == Some(workspace_path))
+    }
+}
+
+/// A mapping between a depot path and a workspace path, see WorkspaceSpec
+/// Each mapping applies to its path along with everything below it, so a depot path of `Content/Movies` matches
+/// `Content/Movies/**`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ViewMapping {
+    pub kind: ViewMappingKind,
+    /// The depot path the mapping applies to
+    pub depot_path: RelativePath,
+    /// The workspace path the depot path is mapped to, ignored for exclusions
+    pub workspace_path: RelativePath,
+}
+
+/// Whether a ViewMapping adds depot paths to the workspace, or removes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ViewMappingKind {
+    /// The depot path is mapped into the workspace
+    Include,
+    /// The depot path is not mapped, even if an earlier mapping included it
+    Exclude,
+}
+
+/// Identifies a shelf, shelves are numbered sequentially as they are created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ShelfId(u64);
+
+impl Display for ShelfId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ShelfId {
+    /// Creates a new ShelfId with the given number
+    pub fn new(number: u64) -> Self {
+        ShelfId(number)
+    }
+
+    /// Returns the number of this shelf
+    pub fn number(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A set of pending changes stored on the server without being submitted, so they can be shared with other
+/// workspaces, see `WorkspaceApi::shelve`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Shelf {
+    pub id: ShelfId,
+    /// The user who created the shelf
+    pub owner: String,
+    /// The description given when the shelf was created
+    pub description: String,
+    /// The time the shelf was created, in Unix milliseconds UTC
+    pub created_time_unix_ms_utc: u64,
+    /// The name of the changelist the changes were shelved from
+    pub changelist: String,
+    /// A tree containing only the shelved files, each with the change state and metadata it was shelved with
+    /// Shelves which are listed rather than fetched only have the root directory loaded, with its aggregated states.
+    pub contents: Directory,
+}
+
+/// A named, fixed set of file revisions below a path, for example the files which shipped in a release, see
+/// `WorkspaceApi::create_label`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Label {
+    /// The unique name of the label
+    pub name: String,
+    /// The description given when the label was created
+    pub description: String,
+    /// The user who created the label
+    pub owner: String,
+    /// The time the label was created, in Unix milliseconds UTC
+    pub created_time_unix_ms_utc: u64,
+    /// The path the labeled files are at or below
+    pub scope: RelativePath,
+    /// The revision of the depot the labeled files were taken from
+    pub revision: Revision,
+}
+
+/// A set of changes submitted together as a single revision, see `WorkspaceApi::fetch_changeset`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Changeset {
+    /// The revision the changes were submitted as
+    pub id: Revision,
+    /// The user who submitted the changes
+    pub author: String,
+    /// The time the changes were submitted, in Unix milliseconds UTC
+    pub submitted_time_unix_ms_utc: u64,
+    /// The description the changes were submitted with
+    pub description: String,
+    /// Every file affected by the changeset, ordered by path
+    pub changes: Vec<ChangesetChange>,
+}
+
+/// A file affected by a Changeset
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChangesetChange {
+    /// The full relative path of the file within the workspace
+    pub path: RelativePath,
+    /// How the file was changed, this is never `ChangeState::Unchanged`
+    /// A moved file is deleted at its old path and added at its new path
+    pub change_state: ChangeState,
+}
+
+/// A submitted revision of a single file, see `WorkspaceApi::file_history`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileRevision {
+    /// The revision the change to the file was submitted in
+    pub revision: Revision,
+    /// The full relative path of the file at this revision, which differs from the requested path before a move
+    pub path: RelativePath,
+    /// The user who submitted the change
+    pub author: String,
+    /// The time the change was submitted, in Unix milliseconds UTC
+    pub submitted_time_unix_ms_utc: u64,
+    /// The description the change was submitted with
+    pub description: String,
+    /// What was done to the file in this revision
+    pub action: FileAction,
+    /// The metadata of the file at this revision, or `None` if the file was deleted
+    pub metadata: Option<FileMetadata>,
+}
+
+/// What was done to a file in a submitted revision, see FileRevision
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FileAction {
+    /// The file was added
+    Add,
+    /// The file was edited
+    Edit,
+    /// The file was deleted
+    Delete,
+    /// The file was moved from the given path, possibly with edits
+    Move { from: RelativePath },
+}
+
+/// The name of the changelist which pending changes belong to unless moved to another changelist
+pub const DEFAULT_CHANGELIST: &str = "default";
+
+/// A named group of pending changes, see `WorkspaceApi::list_pending_changes`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Changelist {
+    /// The name of the changelist, `DEFAULT_CHANGELIST` for the default changelist
+    pub name: String,
+    /// The pending changes within this changelist, ordered by path
+    pub changes: Vec<PendingChange>,
+}
+
+/// A file with pending changes in the workspace
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PendingChange {
+    /// The full relative path of the file within the workspace
+    pub path: RelativePath,
+    /// The change state of the file, this is never `ChangeState::Unchanged`
+    pub change_state: ChangeState,
+    /// The metadata of the file
+    pub metadata: FileMetadata,
+}
+
+/// A file whose pending changes were reverted, see `WorkspaceApi::revert`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RevertedFile {
+    /// The full relative path of the file within the workspace
+    pub path: RelativePath,
+    /// The change state of the file before it was reverted
+    pub reverted_change_state: ChangeState,
+    /// The entry after reverting, or `None` if the file was opened for add, and so was removed from the workspace
+    pub entry: Option<DirectoryEntry>,
+}
+
+/// A change to a file which syncing the workspace would make, see `WorkspaceApi::preview_sync`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IncomingChange {
+    /// The full relative path of the file within the workspace
+    pub path: RelativePath,
+    /// What syncing would do to the file
+    pub action: SyncAction,
+    /// The metadata of the incoming version of the file, or `None` if the incoming change deletes it
+    pub metadata: Option<FileMetadata>,
+}
+
+impl IncomingChange {
+    /// Returns the number of bytes syncing the change would transfer
+    pub fn size_bytes(&self) -> u64 {
+        self.metadata.as_ref().map_or(0, FileMetadata::size_bytes)
+    }
+}
+
+/// What syncing does to a file, see IncomingChange
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SyncAction {
+    /// The file is new in the depot and will be added to the workspace
+    Add,
+    /// The file was changed in the depot and will be updated in the workspace
+    Update,
+    /// The file was deleted from the depot and will be removed from the workspace
+    Delete,
+    /// The file was changed or deleted in the depot while it has pending changes in the workspace, so syncing leaves
+    /// an unresolved conflict instead
+    Conflict,
+}
+
+/// The progress of a sync, yielded as each file is synced, see `WorkspaceApi::sync`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SyncProgress {
+    /// The change which was just synced
+    pub change: IncomingChange,
+    /// The number of files synced so far, including this one
+    pub files_done: u64,
+    /// The number of files the sync will sync in total
+    pub files_total: u64,
+    /// The number of bytes transferred so far, including this file
+    pub bytes_done: u64,
+    /// The number of bytes the sync will transfer in total
+    pub bytes_total: u64,
+}
+
+impl SyncProgress {
+    /// Returns whether this is the progress of the last file of the sync
+    pub fn is_complete(&self) -> bool {
+        self.files_done == self.files_total
+    }
+}
+
+/// An event describing a change to an entry in the workspace, see `WorkspaceApi::watch`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WatchEvent {
+    /// The kind of change that occurred
+    pub kind: WatchEventKind,
+    /// The full relative path of the changed entry within the workspace
+    pub path: RelativePath,
+    /// The entry after the change, or the last known entry for `WatchEventKind::EntryRemoved`
+    /// Directory entries are always unloaded
+    pub entry: DirectoryEntry,
+}
+
+/// The kind of change described by a WatchEvent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum WatchEventKind {
+    /// A new entry was added
+    EntryAdded,
+    /// An entry was removed
+    EntryRemoved,
+    /// The change state of a file changed
+    ChangeStateChanged,
+    /// The conflict state of a file changed
+    ConflictStateChanged,
+    /// The metadata of a file changed
+    MetadataChanged,
+    /// The lock state or lock owner of a file changed
+    LockStateChanged,
+}
+
+/// The number of files in each state of type `T`, for example the number of modified files below a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct StateCounts<T: EnumSetType + Ord>(BTreeMap<T, u64>);
+
+impl<T: EnumSetType + Ord> Default for StateCounts<T> {
+    fn default() -> Self {
+        StateCounts(BTreeMap::new())
+    }
+}
+
+impl<T: EnumSetType + Ord> StateCounts<T> {
+    /// Returns the number of files in the given state
+    pub fn get(&self, state: T) -> u64 {
+        self.0.get(&state).copied().unwrap_or(0)
+    }
+
+    /// Returns the total number of files across all states
+    pub fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    /// Returns the set of states with a non-zero count
+    pub fn states(&self) -> EnumSet<T> {
+        self.0.keys().copied().collect()
+    }
+
+    /// Returns an iterator over the states with a non-zero count, and their counts
+    pub fn iter(&self) -> impl Iterator<Item = (T, u64)> + '_ {
+        self.0.iter().map(|(state, count)| (*state, *count))
+    }
+
+    fn increment(&mut self, state: T) {
+        *self.0.entry(state).or_default() += 1;
+    }
+
+    fn add_all(&mut self, other: &StateCounts<T>) {
+        for (state, count) in other.iter() {
+            *self.0.entry(state).or_default() += count;
+        }
+    }
+}
+
 /// The change state of a directory entry, e.g. whether it is added, modified, deleted, or unchanged
-#[derive(Default, Debug, Hash, EnumSetType)]
+#[derive(Default, Debug, Hash, PartialOrd, Ord, EnumSetType)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", enumset(serialize_repr = "list"))]
 pub enum ChangeState {
@@ -183,9 +1374,8 @@ pub enum ChangeState {
 }
 
 /// The conflict state of a directory entry
-/// Note, this will be updated to include metadata about the conflict, for example, who published the conflicting
-/// change, timestamps, etc.
-#[derive(Default, Debug, Hash, EnumSetType)]
+/// Details of an unresolved or resolved conflict are described by a ConflictInfo, see `WorkspaceApi::list_conflicts`
+#[derive(Default, Debug, Hash, PartialOrd, Ord, EnumSetType)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", enumset(serialize_repr = "list"))]
 pub enum ConflictState {
@@ -196,10 +1386,96 @@ pub enum ConflictState {
     Unresolved,
     /// The entry's conflicts have been resolved
     Resolved,
-    /// The entry has incoming changes that conflict with local changes
+    /// The entry has incoming changes submitted by others which have not been synced yet, a file with pending
+    /// changes can't be submitted until it is synced
     Incoming,
 }
 
+/// The state of the lock on a file, as aggregated by directories, see Lock
+#[derive(Default, Debug, Hash, PartialOrd, Ord, EnumSetType)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", enumset(serialize_repr = "list"))]
+pub enum LockState {
+    /// The file is not locked
+    #[default]
+    Unlocked,
+    /// The file is locked by the current user
+    LockedByYou,
+    /// The file is locked by another user, so can't be opened by the current user
+    LockedByOther,
+}
+
+/// The lock on a file, locked files can only be opened for edit, delete or move by the lock owner
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Lock {
+    /// The file is not locked
+    #[default]
+    Unlocked,
+    /// The file is locked by the current user
+    LockedByYou,
+    /// The file is locked by the given user, so can't be opened by the current user
+    LockedByOther { owner: String },
+}
+
+impl Lock {
+    /// Returns the state of the lock, without the owner
+    pub fn state(&self) -> LockState {
+        match self {
+            Lock::Unlocked => LockState::Unlocked,
+            Lock::LockedByYou => LockState::LockedByYou,
+            Lock::LockedByOther { .. } => LockState::LockedByOther,
+        }
+    }
+}
+
+/// A lock held on a file, see `WorkspaceApi::list_locks`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileLock {
+    /// The full relative path of the locked file within the workspace
+    pub path: RelativePath,
+    /// The lock on the file, this is never `Lock::Unlocked`
+    pub lock: Lock,
+}
+
+/// Details of a conflict between a file in the workspace and a change published by another user
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConflictInfo {
+    /// The full relative path of the conflicted file within the workspace
+    pub path: RelativePath,
+    /// The kind of conflict
+    pub kind: ConflictKind,
+    /// Whether the conflict is unresolved or resolved, this is never `ConflictState::None` or
+    /// `ConflictState::Incoming`
+    pub conflict_state: ConflictState,
+    /// The revision of the common ancestor of both versions of the file
+    pub base_revision: Revision,
+    /// The revision containing the conflicting change
+    pub theirs_revision: Revision,
+    /// The revision the workspace version of the file is based on
+    pub yours_revision: Revision,
+    /// The user who published the conflicting change
+    pub published_by: String,
+    /// The time the conflicting change was published, in Unix milliseconds UTC
+    pub published_time_unix_ms_utc: u64,
+    /// The metadata of their version of the file, or `None` if they deleted it
+    pub theirs_metadata: Option<FileMetadata>,
+}
+
+/// The kind of a conflict, see ConflictInfo
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ConflictKind {
+    /// Both versions of the file have been edited
+    Content,
+    /// One version of the file has been deleted while the other has been edited
+    DeleteVsEdit,
+    /// They renamed the file to the given path while it was edited or renamed in the workspace
+    Rename { theirs_path: RelativePath },
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -212,6 +1488,7 @@ pub mod tests {
                 metadata: FileMetadata::new(100, 1620000000000),
                 change_state: ChangeState::Added,
                 conflict_state: ConflictState::None,
+                lock: Lock::Unlocked,
             },
         );
 
@@ -221,6 +1498,9 @@ pub mod tests {
                 metadata: FileMetadata::new(200, 1620000001000),
                 change_state: ChangeState::Modified,
                 conflict_state: ConflictState::Unresolved,
+                lock: Lock::LockedByOther {
+                    owner: "someone".into(),
+                },
             },
         );
 
@@ -251,6 +1531,119 @@ pub mod tests {
         });
         assert_eq!(dir.change_states, dir2.change_states);
         assert_eq!(dir.conflict_states, dir2.conflict_states);
+
+        // Per-state counts should include files in sub-directories
+        assert_eq!(dir.change_state_counts().get(ChangeState::Added), 1);
+        assert_eq!(dir.change_state_counts().get(ChangeState::Modified), 1);
+        assert_eq!(dir.change_state_counts().get(ChangeState::Deleted), 0);
+        assert_eq!(dir.change_state_counts().total(), 2);
+        assert_eq!(dir.conflict_state_counts().get(ConflictState::None), 1);
+        assert_eq!(dir.conflict_state_counts().get(ConflictState::Unresolved), 1);
+        assert_eq!(dir.change_state_counts().states(), dir.change_states());
+        assert_eq!(dir.conflict_state_counts().states(), dir.conflict_states());
+        assert_eq!(dir.change_state_counts(), dir2.change_state_counts());
+        assert_eq!(dir.conflict_state_counts(), dir2.conflict_state_counts());
+
+        // Lock states aggregate in the same way
+        assert_eq!(dir.lock_states(), LockState::Unlocked | LockState::LockedByOther);
+        assert_eq!(dir.lock_state_counts().get(LockState::LockedByOther), 1);
+        assert_eq!(dir.lock_state_counts(), dir2.lock_state_counts());
+    }
+
+    #[test]
+    fn test_map_depot_path() {
+        let path = |path: &str| RelativePath::new(path).unwrap();
+        let mapping = |kind, depot_path: &str, workspace_path: &str| ViewMapping {
+            kind,
+            depot_path: path(depot_path),
+            workspace_path: path(workspace_path),
+        };
+        let mut spec = WorkspaceSpec {
+            name: "sparse".to_string(),
+            owner: "alice".to_string(),
+            root: PathBuf::from("/work/sparse"),
+            view: WorkspaceSpec::full_view(),
+        };
+        assert_eq!(
+            spec.map_depot_path(&path("Content/Hero.uasset")),
+            Some(path("Content/Hero.uasset"))
+        );
+
+        spec.view.push(mapping(ViewMappingKind::Exclude, "Content/Movies", ""));
+        spec.view
+            .push(mapping(ViewMappingKind::Include, "Content/Movies/Intro", "Intro"));
+        assert_eq!(spec.map_depot_path(&path("Content/Movies/Trailer.mp4")), None);
+        assert_eq!(spec.map_depot_path(&path("Content/Movies")), None);
+        assert_eq!(
+            spec.map_depot_path(&path("Content/MoviesExtra/Clip.mp4")),
+            Some(path("Content/MoviesExtra/Clip.mp4")),
+            "Mappings should only match whole components"
+        );
+        assert_eq!(
+            spec.map_depot_path(&path("Content/Movies/Intro/Logo.mp4")),
+            Some(path("Intro/Logo.mp4")),
+            "Later mappings should override earlier ones"
+        );
+        assert_eq!(
+            spec.map_workspace_path(&path("Intro/Logo.mp4")),
+            Some(path("Content/Movies/Intro/Logo.mp4"))
+        );
+        assert_eq!(spec.map_workspace_path(&path("Content/Movies/Trailer.mp4")), None);
+        assert_eq!(spec.map_workspace_path(&path("Content")), Some(path("Content")));
+
+        spec.view.clear();
+        assert_eq!(spec.map_depot_path(&path("Content/Hero.uasset")), None);
+    }
+
+    #[test]
+    fn test_retain_recursive() {
+        let mut root_dir_entry = DirectoryEntry::new(
+            "".into(),
+            DirectoryEntryType::Directory(Some(Directory::new(RelativePath::new("").unwrap(), vec![]))),
+        );
+
+        let mut subdir_a = new_dir(&root_dir_entry, "subdir_a");
+        push_entry(&mut subdir_a, new_file_with_state("added.txt", ChangeState::Added));
+        push_entry(
+            &mut subdir_a,
+            new_file_with_state("match_modified.txt", ChangeState::Modified),
+        );
+        push_entry(&mut root_dir_entry, subdir_a);
+
+        let mut subdir_b = new_dir(&root_dir_entry, "subdir_b");
+        push_entry(&mut subdir_b, new_file_with_state("deleted.txt", ChangeState::Deleted));
+        push_entry(&mut root_dir_entry, subdir_b);
+
+        let mut match_dir = new_dir(&root_dir_entry, "match_dir");
+        push_entry(&mut match_dir, new_file_with_state("kept.txt", ChangeState::Deleted));
+        push_entry(&mut root_dir_entry, match_dir);
+
+        let root_directory = match &mut root_dir_entry.info {
+            DirectoryEntryType::Directory(Some(dir)) => dir,
+            _ => panic!("Root should be a directory"),
+        };
+        assert!(root_directory.change_states.contains(ChangeState::Added));
+
+        root_directory.retain_recursive(&mut |entry| entry.name().starts_with("match"));
+
+        let mut names = vec![];
+        collect_names(root_directory, &mut names);
+        assert_eq!(
+            names,
+            vec![
+                "subdir_a",
+                "subdir_a/match_modified.txt",
+                "match_dir",
+                "match_dir/kept.txt",
+            ]
+        );
+
+        // Aggregates should only reflect the kept entries
+        assert_eq!(
+            root_directory.change_states,
+            ChangeState::Modified | ChangeState::Deleted,
+            "Aggregated change states should be recomputed over the kept entries"
+        );
     }
 
     #[test]
@@ -314,6 +1707,9 @@ pub mod tests {
             ]
         );
 
+        let unpruned_counts = root_directory.change_state_counts().clone();
+        assert_eq!(unpruned_counts.get(ChangeState::Unchanged), 4);
+
         // Prune to depth 3
         // This should remove subdir_a_l4 and its contents, but keep everything else
         // subdir_a_l4 SHOULD still be in the list, but its contents should be gone
@@ -364,6 +1760,232 @@ pub mod tests {
             names,
             vec!["file_root.txt", "subdir_a_l1 (unloaded)", "subdir_b_l1 (unloaded)",]
         );
+
+        // Pruning should not affect the aggregated counts, as they still describe the unloaded contents
+        assert_eq!(root_directory.change_state_counts(), &unpruned_counts);
+    }
+
+    #[test]
+    fn test_diff_directories() {
+        let file = |name: &str, size_bytes: u64| {
+            DirectoryEntry::new(
+                name.to_string(),
+                DirectoryEntryType::File {
+                    metadata: FileMetadata::new(size_bytes, 0),
+                    change_state: ChangeState::Unchanged,
+                    conflict_state: ConflictState::None,
+                    lock: Lock::Unlocked,
+                },
+            )
+        };
+        let dir = |path: &str, entries: Vec<DirectoryEntry>| {
+            let relative_path = RelativePath::new(path).unwrap();
+            DirectoryEntry::new(
+                relative_path.file_name().unwrap().to_string(),
+                DirectoryEntryType::Directory(Some(Directory::new(relative_path, entries))),
+            )
+        };
+
+        let from = Directory::new(
+            RelativePath::new("").unwrap(),
+            vec![
+                file("a.txt", 1),
+                file("b.txt", 2),
+                dir("changed", vec![file("z.txt", 1)]),
+                dir("old", vec![file("y.txt", 1)]),
+                dir("flip", vec![file("u.txt", 1)]),
+                dir("same", vec![file("x.txt", 1)]),
+                file("swap", 1),
+            ],
+        );
+        let to = Directory::new(
+            RelativePath::new("").unwrap(),
+            vec![
+                file("a.txt", 1),
+                file("b.txt", 3),
+                file("c.txt", 1),
+                dir(
+                    "changed",
+                    vec![
+                        file("z.txt", 2),
+                        DirectoryEntry::new("unloaded".into(), DirectoryEntryType::Directory(None)),
+                    ],
+                ),
+                file("flip", 1),
+                dir("new", vec![file("w.txt", 1)]),
+                dir("same", vec![file("x.txt", 1)]),
+                dir("swap", vec![file("v.txt", 1)]),
+            ],
+        );
+
+        let mut diff = diff_directories(&from, &to);
+        let changes = diff
+            .files()
+            .map(|(path, entry)| match entry.info() {
+                DirectoryEntryType::File { change_state, .. } => (path.to_string(), *change_state),
+                DirectoryEntryType::Directory(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("b.txt".to_string(), ChangeState::Modified),
+                ("c.txt".to_string(), ChangeState::Added),
+                ("changed/z.txt".to_string(), ChangeState::Modified),
+                ("flip".to_string(), ChangeState::Modified),
+                ("new/w.txt".to_string(), ChangeState::Added),
+                ("old/y.txt".to_string(), ChangeState::Deleted),
+                ("swap/v.txt".to_string(), ChangeState::Added),
+            ],
+            "Only differing files should be included, along with their ancestors, and type changes as they are in `to`"
+        );
+        let names = diff.entries().iter().map(DirectoryEntry::name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["b.txt", "c.txt", "changed", "flip", "new", "old", "swap"]);
+        assert!(
+            matches!(
+                diff.entry("flip").map(DirectoryEntry::info),
+                Some(DirectoryEntryType::File {
+                    change_state: ChangeState::Modified,
+                    ..
+                })
+            ),
+            "A file replacing a directory should be found as a modified file"
+        );
+        assert!(
+            matches!(
+                diff.entry("swap").map(DirectoryEntry::info),
+                Some(DirectoryEntryType::Directory(Some(_)))
+            ),
+            "A directory replacing a file should be found as a directory"
+        );
+        assert!(diff.entry("old").is_some() && diff.entry("new").is_some());
+        let changed = diff.find_directory(&RelativePath::new("changed").unwrap()).unwrap();
+        assert_eq!(changed.entries().len(), 1, "Unloaded directories should be skipped");
+        assert!(
+            matches!(
+                diff.entry("b.txt").map(DirectoryEntry::info),
+                Some(DirectoryEntryType::File { metadata, .. }) if metadata.size_bytes() == 3
+            ),
+            "Modified files should have the metadata of their newest version"
+        );
+
+        // Aggregation and pruning work on diffs as on any other directory
+        assert_eq!(
+            diff.change_states(),
+            ChangeState::Added | ChangeState::Modified | ChangeState::Deleted
+        );
+        assert_eq!(diff.change_state_counts().get(ChangeState::Added), 3);
+        diff.prune_to_depth(0);
+        assert_eq!(diff.change_state_counts().get(ChangeState::Modified), 3);
+        assert_eq!(diff.change_state_counts().get(ChangeState::Deleted), 1);
+
+        let diff = diff_directories(&to, &to);
+        assert!(diff.entries().is_empty(), "Identical trees should have no differences");
+    }
+
+    #[test]
+    fn test_diff_file_contents() {
+        let path = RelativePath::new("notes.txt").unwrap();
+        let from = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let to = "a\nB\nc\nd\ne\nf\ng\nh\ni\nk\n";
+
+        let diff = diff_file_contents(path.clone(), Some(from.as_bytes()), Some(to.as_bytes()), 1);
+        assert!(diff.differs);
+        assert!(!diff.is_binary);
+        assert_eq!(diff.hunks.len(), 2, "Distant changes should be in separate hunks");
+        assert_eq!(
+            (
+                diff.hunks[1].from_start,
+                diff.hunks[1].from_count,
+                diff.hunks[1].to_start,
+                diff.hunks[1].to_count
+            ),
+            (9, 2, 9, 2)
+        );
+        assert_eq!(
+            diff.hunks[0].lines[1],
+            DiffLine {
+                kind: DiffLineKind::Removed,
+                text: "b".to_string(),
+                missing_newline: false,
+            }
+        );
+        assert_eq!(
+            diff.to_unified(),
+            "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -9,2 +9,2 @@\n i\n-j\n+k\n"
+        );
+
+        let diff = diff_file_contents(path.clone(), Some(from.as_bytes()), Some(to.as_bytes()), 4);
+        assert_eq!(diff.hunks.len(), 1, "Changes with overlapping context should be merged");
+        assert_eq!((diff.hunks[0].from_start, diff.hunks[0].from_count), (1, 10));
+
+        let diff = diff_file_contents(path.clone(), None, Some(b"x\ny\n"), DIFF_CONTEXT_LINES);
+        assert_eq!(
+            diff.to_unified(),
+            "--- /dev/null\n+++ b/notes.txt\n@@ -0,0 +1,2 @@\n+x\n+y\n"
+        );
+
+        let diff = diff_file_contents(
+            path.clone(),
+            Some(from.as_bytes()),
+            Some(from.as_bytes()),
+            DIFF_CONTEXT_LINES,
+        );
+        assert!(!diff.differs);
+        assert!(diff.hunks.is_empty());
+        assert_eq!(diff.to_unified(), "");
+
+        let diff = diff_file_contents(path.clone(), Some(b"\0\x01"), Some(b"\0\x02"), DIFF_CONTEXT_LINES);
+        assert!(diff.is_binary);
+        assert!(diff.hunks.is_empty());
+        assert_eq!(diff.to_unified(), "Binary files a/notes.txt and b/notes.txt differ\n");
+
+        // Line terminators are part of each line
+        let diff = diff_file_contents(path.clone(), Some(b"x"), Some(b"x\n"), DIFF_CONTEXT_LINES);
+        assert_eq!(
+            diff.to_unified(),
+            "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,1 +1,1 @@\n-x\n\\ No newline at end of file\n+x\n"
+        );
+        let diff = diff_file_contents(path.clone(), Some(b"x\r\ny\n"), Some(b"x\ny\n"), DIFF_CONTEXT_LINES);
+        assert_eq!(diff.hunks.len(), 1);
+        assert_eq!(
+            diff.to_unified(),
+            "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,2 +1,2 @@\n-x\r\n+x\n y\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_large_file_contents() {
+        let path = RelativePath::new("large.txt").unwrap();
+        let lines = |prefix: &str, count: usize| (0..count).map(|index| format!("{}{}\n", prefix, index)).collect();
+
+        // Scattered edits through a large file are found without a quadratic table
+        let from: String = lines("line ", 100_000);
+        let to = from.replace("line 10\n", "edited 10\n").replace("line 90000\n", "");
+        let diff = diff_file_contents(path.clone(), Some(from.as_bytes()), Some(to.as_bytes()), 0);
+        assert_eq!(diff.hunks.len(), 2);
+        assert_eq!(
+            (
+                diff.hunks[1].from_start,
+                diff.hunks[1].from_count,
+                diff.hunks[1].to_count
+            ),
+            (90001, 1, 0)
+        );
+
+        // Versions needing too many edits are diffed as a whole replacement
+        let from: String = lines("a", MAX_DIFF_EDITS);
+        let to: String = lines("b", MAX_DIFF_EDITS);
+        let diff = diff_file_contents(path, Some(from.as_bytes()), Some(to.as_bytes()), DIFF_CONTEXT_LINES);
+        assert_eq!(diff.hunks.len(), 1);
+        let kinds = diff.hunks[0].lines.iter().map(|line| line.kind).collect::<Vec<_>>();
+        assert_eq!(kinds.len(), 2 * MAX_DIFF_EDITS);
+        assert!(
+            kinds[..MAX_DIFF_EDITS]
+                .iter()
+                .all(|kind| *kind == DiffLineKind::Removed)
+        );
+        assert!(kinds[MAX_DIFF_EDITS..].iter().all(|kind| *kind == DiffLineKind::Added));
     }
 
     fn collect_names(dir: &Directory, names: &mut Vec<String>) {
@@ -404,12 +2026,17 @@ pub mod tests {
     }
 
     fn new_file(name: &str) -> DirectoryEntry {
+        new_file_with_state(name, ChangeState::default())
+    }
+
+    fn new_file_with_state(name: &str, change_state: ChangeState) -> DirectoryEntry {
         DirectoryEntry::new(
             name.to_string(),
             DirectoryEntryType::File {
                 metadata: FileMetadata::new(0, 0),
-                change_state: ChangeState::default(),
+                change_state,
                 conflict_state: ConflictState::default(),
+                lock: Lock::Unlocked,
             },
         )
     }